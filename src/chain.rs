@@ -0,0 +1,1815 @@
+use rand::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
+    fmt::{Debug, Write},
+    hash::Hash,
+    sync::Arc,
+};
+
+use crate::{
+    MarkovError,
+    linalg::{identity, mat_mul, solve, stationary},
+    step::{Step, ToStep, WalkIter, walk_seeded, walk_with_rng},
+};
+
+/// Tolerance used when checking that a probability distribution sums to `1.0`.
+const DISTRIBUTION_TOLERANCE: f64 = 1e-6;
+
+/// Number of simulated walker pairs used when estimating coupling times.
+const COUPLING_TRIALS: usize = 500;
+
+/// Number of steps after which a pair of walkers that has not met is considered uncoupled.
+const COUPLING_STEP_LIMIT: usize = 10_000;
+
+/// Largest number of observations redrawn per step in a bootstrap replicate.
+const BOOTSTRAP_OBSERVATION_LIMIT: usize = 10_000;
+
+/// Integer weight given to a transition of probability `1.0` when converting probabilities back into weights.
+const PROBABILITY_SCALE: f64 = 1_000_000.0;
+
+/// A registry of steps keyed by their state.
+///
+/// Methods taking a `seed` build on `walk_seeded` and share its guarantee: results repeat for
+/// the same instance within one process, but not for a copy built from the same weights.
+/// Transitions to steps that are not registered, e.g. added through `Step::insert_transition`,
+/// are ignored by the matrix and graph methods.
+pub struct Chain<T: Eq + Copy + Hash + Debug + Send + Sync> {
+    steps: HashMap<T, ToStep<T>>,
+}
+
+impl<T> Default for Chain<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+{
+    fn default() -> Self {
+        Chain::new()
+    }
+}
+
+impl<'a> Chain<&'a [&'a str]> {
+    /// Build an `order`-th order chain whose states are runs of `order` consecutive words.
+    ///
+    /// Each pair of overlapping n-grams adds one to the weight between them. States borrow
+    /// from `words` so they stay `Copy`; tokenize the corpus first, e.g. with
+    /// `text.split_whitespace().collect::<Vec<_>>()`.
+    ///
+    /// # Examples:
+    /// ```
+    /// use linked_markov::Chain;
+    ///
+    /// let words: Vec<&str> = "the cat sat on the cat mat".split_whitespace().collect();
+    /// let chain = Chain::from_corpus_with_order(&words, 2);
+    /// assert_eq!(chain.edges_between(&["the", "cat"], &["cat", "sat"]), Some(1));
+    /// ```
+    pub fn from_corpus_with_order(words: &'a [&'a str], order: usize) -> Self {
+        let mut chain = Chain::new();
+        if order == 0 {
+            return chain;
+        }
+        let ngrams: Vec<&'a [&'a str]> = words.windows(order).collect();
+        for &ngram in &ngrams {
+            chain.insert_state(ngram);
+        }
+        for pair in ngrams.windows(2) {
+            let to_step = chain.steps[pair[1]].clone();
+            *chain.steps[pair[0]]
+                .transitions
+                .write()
+                .unwrap()
+                .entry(to_step)
+                .or_insert(0) += 1;
+        }
+        chain
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+{
+    /// Create an empty `Chain`.
+    pub fn new() -> Self {
+        Chain {
+            steps: HashMap::new(),
+        }
+    }
+
+    /// Create a star with transitions from `center` to each leaf and from each leaf back to `center`.
+    pub fn star_chain(center: T, leaves: &[T], in_weight: usize, out_weight: usize) -> Self {
+        let mut chain = Chain::new();
+        chain.insert_state(center);
+        for &leaf in leaves {
+            chain.insert_transition(center, leaf, out_weight);
+            chain.insert_transition(leaf, center, in_weight);
+        }
+        chain
+    }
+
+    /// Create a linear chain `states[0] → states[1] → ... → states[n-1]` with unit weights.
+    ///
+    /// The last state has no outgoing transitions and is therefore absorbing.
+    pub fn path_chain(states: &[T]) -> Self {
+        let mut chain = Chain::new();
+        for &state in states {
+            chain.insert_state(state);
+        }
+        for pair in states.windows(2) {
+            chain.insert_transition(pair[0], pair[1], 1);
+        }
+        chain
+    }
+
+    /// Create a complete bipartite chain where every state in `a` transitions to every state in
+    /// `b` and vice versa, all with unit weight.
+    pub fn complete_bipartite_chain(a: &[T], b: &[T]) -> Self {
+        let mut chain = Chain::new();
+        for &from in a {
+            for &to in b {
+                chain.insert_transition(from, to, 1);
+                chain.insert_transition(to, from, 1);
+            }
+        }
+        chain
+    }
+
+    /// Create a random chain where each directed edge, self-loops included, exists
+    /// independently with probability `density` and has a weight drawn uniformly from `1..=10`.
+    ///
+    /// `density` is clamped to `0.0..=1.0` and a NaN density is treated as `0.0`. The same
+    /// `states` and `seed` always produce the same edges and weights.
+    pub fn random_chain(states: &[T], density: f64, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let density = if density.is_nan() {
+            0.0
+        } else {
+            density.clamp(0.0, 1.0)
+        };
+        let mut chain = Chain::new();
+        for &from in states {
+            chain.insert_state(from);
+            for &to in states {
+                if rng.random_bool(density) {
+                    chain.insert_transition(from, to, rng.random_range(1..=10));
+                }
+            }
+        }
+        chain
+    }
+
+    /// Build a chain from a map of every state to its outgoing `(target, weight)` pairs.
+    ///
+    /// This is the inverse of `to_adjacency_list`. Targets that are not keys of `adj` are
+    /// registered as well, and a repeated target keeps its last weight.
+    pub fn from_adjacency_list(adj: HashMap<T, Vec<(T, usize)>>) -> Self {
+        let mut chain = Chain::new();
+        for (from, targets) in adj {
+            chain.insert_state(from);
+            for (to, weight) in targets {
+                chain.insert_transition(from, to, weight);
+            }
+        }
+        chain
+    }
+
+    /// Return the step registered for `state`, registering a new one if needed.
+    pub fn insert_state(&mut self, state: T) -> ToStep<T> {
+        self.steps
+            .entry(state)
+            .or_insert_with(|| Arc::new(Step::new(state)))
+            .clone()
+    }
+
+    /// Add or update a transition between two states, registering either if needed.
+    pub fn insert_transition(&mut self, from: T, to: T, weight: usize) {
+        let to_step = self.insert_state(to);
+        self.insert_state(from).insert_transition(to_step, weight);
+    }
+
+    /// Look up the step registered for `state`.
+    pub fn state_to_step(&self, state: T) -> Option<ToStep<T>> {
+        self.steps.get(&state).cloned()
+    }
+
+    /// Iterate over all registered states.
+    pub fn states(&self) -> impl Iterator<Item = T> + '_ {
+        self.steps.keys().copied()
+    }
+
+    /// Return the number of registered states.
+    pub fn state_count(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Return the number of transitions across all steps.
+    pub fn edge_count(&self) -> usize {
+        self.steps
+            .values()
+            .map(|step| step.transitions.read().unwrap().len())
+            .sum()
+    }
+
+    /// Return the transition probability matrix along with the state for each row and column.
+    ///
+    /// Each row is normalized over the transitions to registered states, and states without
+    /// such outgoing weight have a row of zeros.
+    pub fn transition_matrix(&self) -> (Vec<T>, Vec<Vec<f64>>) {
+        let (states, index) = self.indexed_states();
+        let matrix = states
+            .iter()
+            .map(|state| {
+                let mut row = vec![0.0; states.len()];
+                let transitions = self.steps[state].transitions.read().unwrap();
+                let registered: Vec<(usize, usize)> = transitions
+                    .iter()
+                    .filter_map(|(to_step, &weight)| Some((*index.get(&to_step.state)?, weight)))
+                    .collect();
+                let total: usize = registered.iter().map(|&(_, weight)| weight).sum();
+                if total > 0 {
+                    for (j, weight) in registered {
+                        row[j] += weight as f64 / total as f64;
+                    }
+                }
+                row
+            })
+            .collect();
+        (states, matrix)
+    }
+
+    /// List the registered states along with a lookup from state to its position in the list.
+    fn indexed_states(&self) -> (Vec<T>, HashMap<T, usize>) {
+        let states: Vec<T> = self.states().collect();
+        let index = states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        (states, index)
+    }
+
+    /// List every transition to a registered state as `(from, to, weight)`.
+    fn edges(&self) -> Vec<(T, T, usize)> {
+        self.steps
+            .iter()
+            .flat_map(|(&from, step)| {
+                let transitions = step.transitions.read().unwrap();
+                transitions
+                    .iter()
+                    .filter(|(to_step, _)| self.steps.contains_key(&to_step.state))
+                    .map(|(to_step, &weight)| (from, to_step.state, weight))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Map every state to the registered states it has a positive-weight transition to.
+    fn adjacency(&self) -> HashMap<T, Vec<T>> {
+        self.steps
+            .iter()
+            .map(|(&state, step)| {
+                let transitions = step.transitions.read().unwrap();
+                let targets = transitions
+                    .iter()
+                    .filter(|&(to_step, &weight)| {
+                        weight > 0 && self.steps.contains_key(&to_step.state)
+                    })
+                    .map(|(to_step, _)| to_step.state)
+                    .collect();
+                (state, targets)
+            })
+            .collect()
+    }
+
+    /// Map every state to the states that have a positive-weight transition to it.
+    fn reverse_adjacency(&self) -> HashMap<T, Vec<T>> {
+        let mut reverse: HashMap<T, Vec<T>> = self.states().map(|s| (s, Vec::new())).collect();
+        for (from, targets) in self.adjacency() {
+            for to in targets {
+                reverse.entry(to).or_default().push(from);
+            }
+        }
+        reverse
+    }
+
+    /// Collect the states reachable from `start` by following the edges in `adjacency`.
+    fn reachable_from(adjacency: &HashMap<T, Vec<T>>, start: T) -> HashSet<T> {
+        let mut visited = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(state) = stack.pop() {
+            for &next in adjacency.get(&state).into_iter().flatten() {
+                if visited.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Find the ordered pair of states with the largest finite shortest-path distance, or `None`
+    /// if the chain is empty.
+    fn farthest_pair(&self) -> Option<(T, T)> {
+        let adjacency = self.adjacency();
+        let mut farthest = None;
+        for start in self.states() {
+            let mut distances = HashMap::from([(start, 0)]);
+            let mut queue = VecDeque::from([start]);
+            while let Some(state) = queue.pop_front() {
+                let distance = distances[&state];
+                if farthest.is_none_or(|(_, _, best)| distance > best) {
+                    farthest = Some((start, state, distance));
+                }
+                for &next in &adjacency[&state] {
+                    if let Entry::Vacant(entry) = distances.entry(next) {
+                        entry.insert(distance + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        farthest.map(|(from, to, _)| (from, to))
+    }
+
+    /// Partition the states into strongly connected components using Kosaraju's algorithm.
+    fn strongly_connected_components(&self) -> Vec<Vec<T>> {
+        let adjacency = self.adjacency();
+        let mut visited = HashSet::new();
+        let mut finished = Vec::with_capacity(adjacency.len());
+        for start in self.states() {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut stack = vec![(start, 0)];
+            while let Some(&(state, i)) = stack.last() {
+                if let Some(&next) = adjacency[&state].get(i) {
+                    stack.last_mut().unwrap().1 += 1;
+                    if visited.insert(next) {
+                        stack.push((next, 0));
+                    }
+                } else {
+                    finished.push(state);
+                    stack.pop();
+                }
+            }
+        }
+
+        let reverse = self.reverse_adjacency();
+        let mut assigned = HashSet::new();
+        let mut components = Vec::new();
+        for &root in finished.iter().rev() {
+            if !assigned.insert(root) {
+                continue;
+            }
+            let mut component = vec![root];
+            let mut stack = vec![root];
+            while let Some(state) = stack.pop() {
+                for &previous in &reverse[&state] {
+                    if assigned.insert(previous) {
+                        component.push(previous);
+                        stack.push(previous);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    /// Unregister the given states and drop every transition leading to them.
+    fn remove_states(&mut self, remove: &HashSet<T>) {
+        self.steps.retain(|state, _| !remove.contains(state));
+        for step in self.steps.values() {
+            step.transitions
+                .write()
+                .unwrap()
+                .retain(|to_step, _| !remove.contains(&to_step.state));
+        }
+    }
+
+    /// Copy the given states into a new chain, keeping only transitions between them.
+    fn subchain(&self, keep: &HashSet<T>) -> Chain<T> {
+        let mut chain = Chain::new();
+        for &state in keep {
+            let Some(step) = self.steps.get(&state) else {
+                continue;
+            };
+            chain.insert_state(state);
+            for (to_step, &weight) in step.transitions.read().unwrap().iter() {
+                if keep.contains(&to_step.state) {
+                    chain.insert_transition(state, to_step.state, weight);
+                }
+            }
+        }
+        chain
+    }
+
+    /// Compute the period of the states reachable from `start`, or `0` if they contain no cycle.
+    ///
+    /// Every state of an irreducible chain shares this period.
+    fn period(&self, start: T) -> usize {
+        fn gcd(a: usize, b: usize) -> usize {
+            if b == 0 { a } else { gcd(b, a % b) }
+        }
+        let adjacency = self.adjacency();
+        let mut levels = HashMap::from([(start, 0usize)]);
+        let mut queue = VecDeque::from([start]);
+        let mut period = 0;
+        while let Some(state) = queue.pop_front() {
+            let level = levels[&state];
+            for &next in &adjacency[&state] {
+                match levels.get(&next) {
+                    Some(&next_level) => period = gcd(period, (level + 1).abs_diff(next_level)),
+                    None => {
+                        levels.insert(next, level + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        period
+    }
+
+    /// Advance two walkers from `a` and `b` with `advance` until they occupy the same state,
+    /// returning the number of steps taken or `None` after `COUPLING_STEP_LIMIT` steps.
+    fn meeting_time<F>(a: &ToStep<T>, b: &ToStep<T>, mut advance: F) -> Option<usize>
+    where
+        F: FnMut(&ToStep<T>, &ToStep<T>) -> (ToStep<T>, ToStep<T>),
+    {
+        let (mut x, mut y) = (a.clone(), b.clone());
+        (0..=COUPLING_STEP_LIMIT).find(|_| {
+            if x == y {
+                return true;
+            }
+            (x, y) = advance(&x, &y);
+            false
+        })
+    }
+
+    /// Walk `steps` states from `start` and return the visit frequencies of all but the first
+    /// `burn_in`, or an empty map if the walk ends during burn-in.
+    fn visit_frequencies(
+        start: ToStep<T>,
+        steps: usize,
+        burn_in: usize,
+        rng: &mut impl Rng,
+    ) -> HashMap<T, f64> {
+        let path = walk_with_rng(start, steps, rng);
+        let kept = path.get(burn_in..).unwrap_or_default();
+        let mut counts: HashMap<T, usize> = HashMap::new();
+        for state in kept {
+            *counts.entry(*state).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(state, count)| (state, count as f64 / kept.len() as f64))
+            .collect()
+    }
+
+    /// Build a chain over the given states whose weights are `probabilities` scaled by
+    /// `PROBABILITY_SCALE`, dropping transitions that round to zero.
+    fn from_probabilities(states: &[T], probabilities: &[Vec<f64>]) -> Self {
+        let mut chain = Chain::new();
+        for (&from, row) in states.iter().zip(probabilities) {
+            chain.insert_state(from);
+            for (&to, &probability) in states.iter().zip(row) {
+                let weight = (probability * PROBABILITY_SCALE).round() as usize;
+                if weight > 0 {
+                    chain.insert_transition(from, to, weight);
+                }
+            }
+        }
+        chain
+    }
+
+    /// Walk the chain for a fixed number of steps, sampling the start state from `init_dist`.
+    ///
+    /// Returns an error if `init_dist` holds a negative or non-finite probability, does not sum
+    /// to `1.0`, or references an unregistered state.
+    pub fn walk_from_distribution(
+        &self,
+        init_dist: &HashMap<T, f64>,
+        steps: usize,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<T>, MarkovError> {
+        if let Some(&invalid) = init_dist.values().find(|p| !p.is_finite() || **p < 0.0) {
+            return Err(MarkovError::InvalidProbability(invalid));
+        }
+        let total: f64 = init_dist.values().sum();
+        if !total.is_finite() || (total - 1.0).abs() > DISTRIBUTION_TOLERANCE {
+            return Err(MarkovError::InvalidDistribution(total));
+        }
+        let mut candidates = Vec::with_capacity(init_dist.len());
+        for (state, &probability) in init_dist {
+            let step = self
+                .state_to_step(*state)
+                .ok_or_else(|| MarkovError::UnknownState(format!("{state:?}")))?;
+            candidates.push((step, probability));
+        }
+
+        let roll = rng.random_range(0.0..total);
+        let mut cumulative = 0.0;
+        let start = candidates
+            .iter()
+            .find(|(_, probability)| {
+                cumulative += probability;
+                roll < cumulative
+            })
+            .or_else(|| candidates.iter().rev().find(|(_, p)| *p > 0.0))
+            .map(|(step, _)| step.clone())
+            .ok_or(MarkovError::InvalidDistribution(total))?;
+        Ok(walk_with_rng(start, steps, rng))
+    }
+
+    /// Check that the outgoing transition probabilities of every state sum to `1.0` within `tolerance`.
+    ///
+    /// States without outgoing weight have no distribution, so their presence yields `false`.
+    pub fn verify_row_stochastic(&self, tolerance: f64) -> bool {
+        self.steps.values().all(|step| {
+            let probabilities = step.transition_probabilities();
+            let total: f64 = probabilities.iter().map(|(_, p)| p).sum();
+            !probabilities.is_empty() && (total - 1.0).abs() <= tolerance
+        })
+    }
+
+    /// Start a lazy walk at `start_state`, or `None` if it is not registered.
+    ///
+    /// # Examples:
+    /// ```
+    /// use linked_markov::Chain;
+    ///
+    /// let mut chain = Chain::new();
+    /// chain.insert_transition("hello", "world", 1);
+    /// chain.insert_transition("world", "hello", 1);
+    /// let path: Vec<_> = chain.lazy_walk("hello").unwrap().take(3).collect();
+    /// assert_eq!(path, vec!["hello", "world", "hello"]);
+    /// ```
+    pub fn lazy_walk(&self, start_state: T) -> Option<WalkIter<T>> {
+        self.state_to_step(start_state).map(WalkIter::new)
+    }
+
+    /// Walk the chain from `start` for a fixed number of steps using an RNG seeded with `seed`.
+    ///
+    /// See `walk_seeded` for when a seed reproduces a path. Returns `None` if `start` is not
+    /// registered.
+    ///
+    /// # Examples:
+    /// ```
+    /// use linked_markov::Chain;
+    ///
+    /// let mut chain = Chain::new();
+    /// chain.insert_transition(false, true, 3);
+    /// chain.insert_transition(false, false, 1);
+    /// chain.insert_transition(true, false, 3);
+    /// chain.insert_transition(true, true, 1);
+    /// let path = chain.walk(false, 100, 42).unwrap();
+    /// assert_eq!(path.len(), 100);
+    /// ```
+    pub fn walk(&self, start: T, steps: usize, seed: u64) -> Option<Vec<T>> {
+        self.state_to_step(start)
+            .map(|step| walk_seeded(step, steps, seed))
+    }
+
+    /// Walk the chain from `start` for a fixed number of steps, applying a function to each
+    /// transition.
+    ///
+    /// Unlike the free `mut_walk`, which records the state each transition leaves, this records
+    /// the state each transition enters, so the path matches `walk` and `lazy_walk`. Returns an
+    /// error if `start` is not registered or if `apply` fails.
+    pub fn mut_walk<F>(&self, start: T, steps: usize, apply: F) -> Result<Vec<T>, MarkovError>
+    where
+        F: Fn(ToStep<T>, ToStep<T>) -> Result<(), MarkovError>,
+    {
+        let mut current = self
+            .state_to_step(start)
+            .ok_or_else(|| MarkovError::UnknownState(format!("{start:?}")))?;
+        let mut path = vec![current.state];
+        for _ in 1..steps {
+            if let Some(next) = current.next() {
+                apply(current.clone(), next.clone())?;
+                path.push(next.state);
+                current = next;
+            } else {
+                break;
+            }
+        }
+        Ok(path)
+    }
+
+    /// Compute the `n`-step chain, whose transition weights are proportional to `P^n`.
+    ///
+    /// A single transition of the result is distributed like `n` transitions of this chain.
+    /// Probabilities are stored as weights scaled to `1_000_000` per unit, and a walk that hits
+    /// a state without outgoing transitions before `n` steps contributes no weight.
+    pub fn power_iteration(&self, n: usize) -> Chain<T> {
+        let (states, matrix) = self.transition_matrix();
+        let mut result = identity(states.len());
+        let mut base = matrix;
+        let mut exponent = n;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = mat_mul(&result, &base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = mat_mul(&base, &base);
+            }
+        }
+        Chain::from_probabilities(&states, &result)
+    }
+
+    /// Compute the probability of being absorbed into `absorbing` when starting from `start`.
+    ///
+    /// Returns `None` if either state is not registered, `absorbing` is not an absorbing state,
+    /// or the chain has no absorbing states.
+    pub fn absorbing_probability(&self, start: T, absorbing: T) -> Option<f64> {
+        let (transient, absorbing_states, b) = self.absorbing_probability_matrix()?;
+        let column = absorbing_states.iter().position(|&s| s == absorbing)?;
+        if let Some(row) = transient.iter().position(|&s| s == start) {
+            return Some(b[row][column]);
+        }
+        absorbing_states
+            .contains(&start)
+            .then_some(if start == absorbing { 1.0 } else { 0.0 })
+    }
+
+    /// Compute the probability of being absorbed into each absorbing state when starting from
+    /// `start`.
+    ///
+    /// The values sum to `1.0` unless `start` can reach a closed class of transient states, in
+    /// which case the missing mass never gets absorbed. Returns `None` if `start` is not
+    /// registered or the chain has no absorbing states.
+    pub fn all_absorbing_probabilities(&self, start: T) -> Option<HashMap<T, f64>> {
+        let (transient, absorbing_states, b) = self.absorbing_probability_matrix()?;
+        if let Some(row) = transient.iter().position(|&s| s == start) {
+            return Some(absorbing_states.into_iter().zip(b[row].clone()).collect());
+        }
+        absorbing_states.contains(&start).then(|| {
+            absorbing_states
+                .iter()
+                .map(|&s| (s, if s == start { 1.0 } else { 0.0 }))
+                .collect()
+        })
+    }
+
+    /// Sample the next state from `state` using top-p (nucleus) sampling.
+    ///
+    /// Outgoing transitions are ranked by probability and the smallest prefix whose cumulative
+    /// probability reaches `p` is kept; the next state is drawn from that prefix in proportion
+    /// to the weights. Ties are ranked in map iteration order, so they decide which of several
+    /// equally likely transitions make the nucleus only for this instance. Returns `None` if `state` is not registered or has no outgoing weight.
+    pub fn sample_top_p_next(&self, state: T, p: f64, rng: &mut impl Rng) -> Option<T> {
+        let mut probabilities = self.steps.get(&state)?.transition_probabilities();
+        if probabilities.is_empty() {
+            return None;
+        }
+        probabilities.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let mut cumulative = 0.0;
+        let nucleus_len = probabilities
+            .iter()
+            .position(|(_, probability)| {
+                cumulative += probability;
+                cumulative >= p - DISTRIBUTION_TOLERANCE
+            })
+            .map_or(probabilities.len(), |i| i + 1);
+        let nucleus = &probabilities[..nucleus_len];
+
+        let total: f64 = nucleus.iter().map(|(_, probability)| probability).sum();
+        let roll = rng.random_range(0.0..total);
+        let mut cumulative = 0.0;
+        nucleus
+            .iter()
+            .find(|(_, probability)| {
+                cumulative += probability;
+                roll < cumulative
+            })
+            .or(nucleus.last())
+            .map(|(to_step, _)| to_step.state)
+    }
+
+    /// Walk the chain from `start` using top-p sampling at every step, seeded with `seed`.
+    ///
+    /// `p = 1.0` samples like `walk`, while `p` close to `0.0` always follows the most likely
+    /// transition. Returns an empty path if `start` is not registered.
+    pub fn walk_top_p(&self, start: T, steps: usize, p: f64, seed: u64) -> Vec<T> {
+        if !self.steps.contains_key(&start) {
+            return Vec::new();
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut current = start;
+        let mut path = vec![current];
+        for _ in 1..steps {
+            if let Some(next) = self.sample_top_p_next(current, p, &mut rng) {
+                path.push(next);
+                current = next;
+            } else {
+                break;
+            }
+        }
+        path
+    }
+
+    /// Multiply every transition weight by `factor`, rounding to the nearest integer.
+    ///
+    /// Transitions whose weight rounds to zero are removed.
+    pub fn scale_all_weights(&mut self, factor: f64) {
+        for step in self.steps.values() {
+            let mut transitions = step.transitions.write().unwrap();
+            transitions.retain(|_, weight| {
+                *weight = (*weight as f64 * factor).round() as usize;
+                *weight > 0
+            });
+        }
+    }
+
+    /// Summarize the chain topology as a multi-line string.
+    ///
+    /// Reports the state and edge counts, out-degree statistics, density (edges over the `n²`
+    /// possible edges) and whether the chain is irreducible, aperiodic and ergodic. Periodicity
+    /// is a property of a single communicating class, so it is reported as `n/a` for a
+    /// reducible chain.
+    pub fn transition_graph_summary(&self) -> String {
+        let states = self.state_count();
+        let edges = self.edge_count();
+        let out_degrees: Vec<usize> = self
+            .steps
+            .values()
+            .map(|step| step.transitions.read().unwrap().len())
+            .collect();
+        let average = self.average_out_degree();
+        let density = if states == 0 {
+            0.0
+        } else {
+            edges as f64 / (states * states) as f64
+        };
+        let irreducible = self.is_strongly_connected();
+        let aperiodic = self
+            .states()
+            .next()
+            .filter(|_| irreducible)
+            .map(|s| self.period(s) == 1);
+
+        let mut summary = String::new();
+        writeln!(summary, "states: {states}").unwrap();
+        writeln!(summary, "edges: {edges}").unwrap();
+        writeln!(
+            summary,
+            "out-degree: avg {average:.2}, min {}, max {}",
+            out_degrees.iter().min().unwrap_or(&0),
+            out_degrees.iter().max().unwrap_or(&0)
+        )
+        .unwrap();
+        writeln!(summary, "density: {density:.4}").unwrap();
+        writeln!(summary, "irreducible: {irreducible}").unwrap();
+        match aperiodic {
+            Some(aperiodic) => writeln!(summary, "aperiodic: {aperiodic}").unwrap(),
+            None => writeln!(summary, "aperiodic: n/a").unwrap(),
+        }
+        write!(summary, "ergodic: {}", aperiodic == Some(true)).unwrap();
+        summary
+    }
+
+    /// Compute the stationary distribution `π` satisfying `π = πP` and summing to `1.0`.
+    ///
+    /// A state without outgoing transitions is treated as absorbing, so on its own it forms a
+    /// closed class. Returns `None` if the chain is empty or has no unique stationary
+    /// distribution, e.g. when it contains several closed classes.
+    pub fn stationary_distribution(&self) -> Option<HashMap<T, f64>> {
+        let (states, matrix) = self.transition_matrix();
+        let solution = stationary(&matrix)?;
+        Some(states.into_iter().zip(solution).collect())
+    }
+
+    /// Walk the chain for `n_steps` steps and return the total variation distance between the
+    /// empirical visit frequencies and the stationary distribution.
+    ///
+    /// The start state is drawn from the stationary distribution itself by an RNG seeded with
+    /// `seed`, so the walk needs no burn-in. For ergodic chains the distance tends to `0.0` as
+    /// `n_steps` grows. Returns `f64::NAN` if there is no unique stationary distribution.
+    pub fn simulate_and_compare_to_stationary(&self, n_steps: usize, seed: u64) -> f64 {
+        let Some(stationary) = self.stationary_distribution() else {
+            return f64::NAN;
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let candidates: Vec<(&ToStep<T>, f64)> = self
+            .steps
+            .values()
+            .map(|step| (step, stationary[&step.state]))
+            .filter(|&(_, probability)| probability > 0.0)
+            .collect();
+        let roll = rng.random_range(0.0..1.0);
+        let mut cumulative = 0.0;
+        let Some((start, _)) = candidates
+            .iter()
+            .find(|(_, probability)| {
+                cumulative += probability;
+                roll < cumulative
+            })
+            .or(candidates.last())
+        else {
+            return f64::NAN;
+        };
+        let empirical = Self::visit_frequencies(Arc::clone(start), n_steps, 0, &mut rng);
+        let l1: f64 = stationary
+            .iter()
+            .map(|(state, probability)| {
+                (empirical.get(state).copied().unwrap_or(0.0) - probability).abs()
+            })
+            .sum();
+        l1 / 2.0
+    }
+
+    /// Add or update both `a → b` and `b → a` with weight `w` for every `(a, b, w)` in `pairs`.
+    pub fn add_transitions_symmetric(&mut self, pairs: &[(T, T, usize)]) {
+        for &(a, b, weight) in pairs {
+            self.insert_transition(a, b, weight);
+            self.insert_transition(b, a, weight);
+        }
+    }
+
+    /// Return the raw transition weight matrix along with the state for each row and column.
+    ///
+    /// Rows and columns are ordered like those of `transition_matrix`.
+    pub fn transition_count_matrix(&self) -> (Vec<T>, Vec<Vec<usize>>) {
+        let (states, index) = self.indexed_states();
+        let matrix = states
+            .iter()
+            .map(|state| {
+                let mut row = vec![0; states.len()];
+                for (to_step, &weight) in self.steps[state].transitions.read().unwrap().iter() {
+                    if let Some(&j) = index.get(&to_step.state) {
+                        row[j] = weight;
+                    }
+                }
+                row
+            })
+            .collect();
+        (states, matrix)
+    }
+
+    /// Check whether every state can reach every other state, i.e. the chain is irreducible.
+    ///
+    /// Runs one search along the transitions and one against them from the same state, which
+    /// is `O(V + E)`. An empty chain is not strongly connected.
+    pub fn is_strongly_connected(&self) -> bool {
+        self.states().next().is_some_and(|start| {
+            Chain::reachable_from(&self.adjacency(), start).len() == self.state_count()
+                && Chain::reachable_from(&self.reverse_adjacency(), start).len()
+                    == self.state_count()
+        })
+    }
+
+    /// Extract the largest strongly connected component as a new chain.
+    ///
+    /// Only transitions between states of the component are kept. Ties between components of
+    /// equal size are broken arbitrarily.
+    pub fn largest_scc(&self) -> Chain<T> {
+        let largest = self
+            .strongly_connected_components()
+            .into_iter()
+            .max_by_key(Vec::len)
+            .unwrap_or_default();
+        self.subchain(&largest.into_iter().collect())
+    }
+
+    /// Rescale the outgoing weights of every step to sum to `target_sum`.
+    ///
+    /// See `Step::normalize_transitions` for how rounding is handled.
+    pub fn normalize_all_weights(&mut self, target_sum: usize) {
+        for step in self.steps.values() {
+            step.normalize_transitions(target_sum);
+        }
+    }
+
+    /// Compute the natural log of the probability of walking `sequence` once its first state is
+    /// reached.
+    ///
+    /// Returns `f64::NEG_INFINITY` if the sequence uses an unregistered state or a missing
+    /// transition, and `0.0` for sequences shorter than two states.
+    pub fn log_probability_of_sequence(&self, sequence: &[T]) -> f64 {
+        sequence
+            .windows(2)
+            .map(|pair| {
+                self.steps
+                    .get(&pair[0])
+                    .and_then(|step| {
+                        step.transition_probabilities()
+                            .into_iter()
+                            .find(|(to_step, _)| to_step.state == pair[1])
+                    })
+                    .map_or(f64::NEG_INFINITY, |(_, probability)| probability.ln())
+            })
+            .sum()
+    }
+
+    /// Generate `n_candidates` seeded walks of `length` steps from `start` and pick one with
+    /// probability proportional to the probability of its path.
+    ///
+    /// Returns an empty path if `start` is not registered or `n_candidates` is zero.
+    pub fn sample_path_proportional_to_probability(
+        &self,
+        start: T,
+        length: usize,
+        n_candidates: usize,
+        seed: u64,
+    ) -> Vec<T> {
+        let Some(start) = self.state_to_step(start) else {
+            return Vec::new();
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let candidates: Vec<(Vec<T>, f64)> = (0..n_candidates)
+            .map(|_| {
+                let path = walk_with_rng(start.clone(), length, &mut rng);
+                let log_probability = self.log_probability_of_sequence(&path);
+                (path, log_probability)
+            })
+            .collect();
+
+        // Shift by the largest log-probability so the exponentials stay representable.
+        let max = candidates
+            .iter()
+            .map(|(_, log_probability)| *log_probability)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|(_, log_probability)| (log_probability - max).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total.is_nan() || total <= 0.0 {
+            return candidates
+                .into_iter()
+                .next()
+                .map_or(Vec::new(), |(path, _)| path);
+        }
+        let roll = rng.random_range(0.0..total);
+        let mut cumulative = 0.0;
+        let chosen = weights
+            .iter()
+            .position(|weight| {
+                cumulative += weight;
+                roll < cumulative
+            })
+            .unwrap_or(candidates.len() - 1);
+        candidates.into_iter().nth(chosen).unwrap().0
+    }
+
+    /// List every `(from, to)` transition stored with a weight of zero.
+    ///
+    /// Zero weights are never chosen by a walk but still count as edges, so they usually point
+    /// to a transition that should have been removed.
+    pub fn verify_transition_weights_positive(&self) -> Vec<(T, T)> {
+        self.edges()
+            .into_iter()
+            .filter(|&(_, _, weight)| weight == 0)
+            .map(|(from, to, _)| (from, to))
+            .collect()
+    }
+
+    /// Merge states whose outgoing transition probabilities are identical within `1e-6`,
+    /// returning the number of states removed.
+    ///
+    /// Incoming transitions of a merged state are redirected to the state it was merged into,
+    /// adding up the weights. Merging repeats until no duplicates remain, since redirecting
+    /// transitions can make further states identical. Zero-weight transitions are ignored when
+    /// comparing, and states without outgoing weight have no distribution and are never merged.
+    pub fn deduplicate_by_transition_pattern(&mut self) -> usize {
+        let mut removed = 0;
+        loop {
+            let distributions: Vec<(T, HashMap<T, f64>)> = self
+                .steps
+                .iter()
+                .map(|(&state, step)| {
+                    let distribution = step
+                        .transition_probabilities()
+                        .into_iter()
+                        .filter(|&(_, probability)| probability > 0.0)
+                        .map(|(to_step, probability)| (to_step.state, probability))
+                        .collect();
+                    (state, distribution)
+                })
+                .filter(|(_, distribution): &(T, HashMap<T, f64>)| !distribution.is_empty())
+                .collect();
+            let mut merged: HashMap<T, T> = HashMap::new();
+            for (i, (keep, keep_distribution)) in distributions.iter().enumerate() {
+                if merged.contains_key(keep) {
+                    continue;
+                }
+                for (other, other_distribution) in &distributions[i + 1..] {
+                    let identical = keep_distribution.len() == other_distribution.len()
+                        && keep_distribution.iter().all(|(to, p)| {
+                            other_distribution
+                                .get(to)
+                                .is_some_and(|q| (p - q).abs() <= DISTRIBUTION_TOLERANCE)
+                        });
+                    if identical && !merged.contains_key(other) {
+                        merged.insert(*other, *keep);
+                    }
+                }
+            }
+            if merged.is_empty() {
+                return removed;
+            }
+
+            for state in merged.keys() {
+                self.steps.remove(state);
+            }
+            for step in self.steps.values() {
+                let mut transitions = step.transitions.write().unwrap();
+                let redirected: Vec<(ToStep<T>, usize)> = transitions
+                    .drain()
+                    .map(|(to_step, weight)| {
+                        let target = merged
+                            .get(&to_step.state)
+                            .and_then(|kept| self.steps.get(kept))
+                            .map_or(to_step, Arc::clone);
+                        (target, weight)
+                    })
+                    .collect();
+                for (to_step, weight) in redirected {
+                    *transitions.entry(to_step).or_insert(0) += weight;
+                }
+            }
+            removed += merged.len();
+        }
+    }
+
+    /// Walk the chain for `steps_per_state` steps from every registered state, drawing all
+    /// walks from one RNG seeded with `seed`.
+    pub fn walk_all_states(&self, steps_per_state: usize, seed: u64) -> HashMap<T, Vec<T>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.steps
+            .iter()
+            .map(|(&state, step)| {
+                (
+                    state,
+                    walk_with_rng(step.clone(), steps_per_state, &mut rng),
+                )
+            })
+            .collect()
+    }
+
+    /// Extract the weakly connected component containing `state` as a new chain, or `None` if
+    /// `state` is not registered.
+    ///
+    /// Two states are weakly connected if a path joins them when transition directions are
+    /// ignored.
+    pub fn connected_subchain_containing(&self, state: T) -> Option<Chain<T>> {
+        if !self.steps.contains_key(&state) {
+            return None;
+        }
+        let mut undirected = self.adjacency();
+        for (to, sources) in self.reverse_adjacency() {
+            undirected.entry(to).or_default().extend(sources);
+        }
+        Some(self.subchain(&Chain::reachable_from(&undirected, state)))
+    }
+
+    /// Return the weight of the transition from `from` to `to`, or `None` if there is none.
+    pub fn edges_between(&self, from: T, to: T) -> Option<usize> {
+        self.state_to_step(from)?
+            .get_transition_weight(self.state_to_step(to)?)
+    }
+
+    /// Estimate how many steps two independent walkers need to meet, as an upper bound on the
+    /// mixing time.
+    ///
+    /// The walkers start from the two states farthest apart and are simulated `500` times with
+    /// an RNG seeded with `seed`. Returns the smallest `t` such that at most a fraction
+    /// `epsilon` of the pairs had not met after `t` steps, or `None` if the chain is empty or
+    /// too many pairs fail to meet within `10_000` steps. A walker stuck in a state without
+    /// outgoing transitions stays there.
+    pub fn mutual_coupling_time(&self, epsilon: f64, seed: u64) -> Option<usize> {
+        let (a, b) = self.farthest_pair()?;
+        let (a, b) = (self.steps[&a].clone(), self.steps[&b].clone());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut times = Vec::with_capacity(COUPLING_TRIALS);
+        for _ in 0..COUPLING_TRIALS {
+            let meeting = Self::meeting_time(&a, &b, |x, y| {
+                (
+                    x.next_with_rng(&mut rng).unwrap_or(x.clone()),
+                    y.next_with_rng(&mut rng).unwrap_or(y.clone()),
+                )
+            });
+            times.extend(meeting);
+        }
+
+        let uncoupled = COUPLING_TRIALS - times.len();
+        let allowed = (epsilon.clamp(0.0, 1.0) * COUPLING_TRIALS as f64).floor() as usize;
+        let slack = allowed.checked_sub(uncoupled)?;
+        times.sort_unstable();
+        Some(times.len().checked_sub(slack + 1).map_or(0, |i| times[i]))
+    }
+
+    /// Compute the chi-squared statistic comparing `empirical` visit frequencies to the
+    /// stationary distribution, scaled to `n_samples` observations.
+    ///
+    /// Only the statistic is returned; look up the critical value for `state_count() - 1`
+    /// degrees of freedom to test it. Returns `f64::INFINITY` if `empirical` puts mass on a
+    /// state with zero stationary probability, and `f64::NAN` if there is no unique stationary
+    /// distribution.
+    pub fn chi_squared_goodness_of_fit(
+        &self,
+        empirical: &HashMap<T, f64>,
+        n_samples: usize,
+    ) -> f64 {
+        let Some(stationary) = self.stationary_distribution() else {
+            return f64::NAN;
+        };
+        let n = n_samples as f64;
+        let unexpected = empirical.iter().any(|(state, &frequency)| {
+            frequency > 0.0 && stationary.get(state).is_none_or(|&p| p <= 0.0)
+        });
+        if unexpected {
+            return f64::INFINITY;
+        }
+        stationary
+            .iter()
+            .filter(|&(_, &probability)| probability > 0.0)
+            .map(|(state, &probability)| {
+                let observed = empirical.get(state).copied().unwrap_or(0.0) * n;
+                let expected = probability * n;
+                (observed - expected).powi(2) / expected
+            })
+            .sum()
+    }
+
+    /// Remove every state whose stationary probability is below `threshold`, along with the
+    /// transitions leading to it, returning the number of states removed.
+    ///
+    /// Transient states have zero stationary probability, so any positive `threshold` removes
+    /// them. Nothing is removed if the chain has no unique stationary distribution.
+    pub fn prune_states_below_stationary(&mut self, threshold: f64) -> usize {
+        let Some(stationary) = self.stationary_distribution() else {
+            return 0;
+        };
+        let remove: HashSet<T> = stationary
+            .into_iter()
+            .filter(|&(_, probability)| probability < threshold)
+            .map(|(state, _)| state)
+            .collect();
+        self.remove_states(&remove);
+        remove.len()
+    }
+
+    /// Add an entry state `init_state` whose outgoing transitions follow `weights`, modelling
+    /// the initial distribution explicitly in the chain.
+    ///
+    /// Target states are registered if needed, while zero weights and a weight for `init_state`
+    /// itself are skipped. Use a fresh `init_state` so that nothing transitions into it and it is
+    /// only ever visited at the start of a walk; existing transitions into it are kept.
+    pub fn add_initial_distribution_state(&mut self, init_state: T, weights: &HashMap<T, usize>) {
+        self.insert_state(init_state);
+        for (&to, &weight) in weights {
+            if weight > 0 && to != init_state {
+                self.insert_transition(init_state, to, weight);
+            }
+        }
+    }
+
+    /// Compute the absorption probabilities of the chain as `(transient, absorbing, B)`, where
+    /// `B[i][j]` is the probability of being absorbed by `absorbing[j]` from `transient[i]`.
+    ///
+    /// `B = NR`, where `N = (I - Q)⁻¹` is the fundamental matrix and `R` holds the
+    /// transient-to-absorbing transition probabilities; it is obtained by solving
+    /// `(I - Q)B = R` directly rather than inverting `I - Q`.
+    /// A state is absorbing if it has no outgoing weight or only transitions to itself.
+    /// Transient states that cannot reach any absorbing state get a row of zeros. Returns `None`
+    /// if the chain has no absorbing states.
+    #[allow(clippy::type_complexity)]
+    pub fn absorbing_probability_matrix(&self) -> Option<(Vec<T>, Vec<T>, Vec<Vec<f64>>)> {
+        let (states, matrix) = self.transition_matrix();
+        let n = states.len();
+        let is_absorbing: Vec<bool> = (0..n)
+            .map(|i| matrix[i][i] == 1.0 || matrix[i].iter().all(|&p| p == 0.0))
+            .collect();
+        let absorbing: Vec<usize> = (0..n).filter(|&i| is_absorbing[i]).collect();
+        if absorbing.is_empty() {
+            return None;
+        }
+        let transient: Vec<usize> = (0..n).filter(|&i| !is_absorbing[i]).collect();
+
+        // Only transient states that can reach absorption yield a non-singular `I - Q`.
+        let mut reaches = is_absorbing.clone();
+        let mut stack = absorbing.clone();
+        while let Some(j) = stack.pop() {
+            for i in 0..n {
+                if !reaches[i] && matrix[i][j] > 0.0 {
+                    reaches[i] = true;
+                    stack.push(i);
+                }
+            }
+        }
+        let solvable: Vec<usize> = transient.iter().copied().filter(|&i| reaches[i]).collect();
+        let i_minus_q = solvable
+            .iter()
+            .map(|&i| {
+                solvable
+                    .iter()
+                    .map(|&j| if i == j { 1.0 } else { 0.0 } - matrix[i][j])
+                    .collect()
+            })
+            .collect();
+        let r = solvable
+            .iter()
+            .map(|&i| absorbing.iter().map(|&j| matrix[i][j]).collect())
+            .collect();
+        let solved = solve(i_minus_q, r)?;
+
+        let mut b = vec![vec![0.0; absorbing.len()]; transient.len()];
+        for (&i, row) in solvable.iter().zip(solved) {
+            let position = transient.iter().position(|&t| t == i).unwrap();
+            b[position] = row;
+        }
+        Some((
+            transient.iter().map(|&i| states[i]).collect(),
+            absorbing.iter().map(|&i| states[i]).collect(),
+            b,
+        ))
+    }
+
+    /// Walk the chain from `start` as an order-`history_len` chain, returning the window of the
+    /// last `history_len` states after every step.
+    ///
+    /// A candidate next state `s` is weighted by `P(x₀, s) · P²(x₁, s) · … · Pᵏ(xₖ₋₁, s)`, where
+    /// `xᵢ` is the state visited `i` steps ago, so states consistent with the whole history are
+    /// favoured. Every state in the history reaches the current state, so any first-order
+    /// successor has positive weight. A `history_len` of `0` is treated as `1`, and the walk
+    /// stops early at a state without outgoing transitions. Returns an empty path if `start`
+    /// is not registered.
+    pub fn walk_with_history(
+        &self,
+        start: T,
+        steps: usize,
+        history_len: usize,
+        seed: u64,
+    ) -> Vec<Vec<T>> {
+        if !self.steps.contains_key(&start) {
+            return Vec::new();
+        }
+        let history_len = history_len.max(1);
+        let (states, index) = self.indexed_states();
+        let (_, matrix) = self.transition_matrix();
+        let mut powers = vec![matrix];
+        while powers.len() < history_len {
+            powers.push(mat_mul(powers.last().unwrap(), &powers[0]));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut history = VecDeque::from([index[&start]]);
+        let mut path = vec![vec![start]];
+        for _ in 1..steps {
+            let weights: Vec<f64> = (0..states.len())
+                .map(|next| {
+                    history
+                        .iter()
+                        .rev()
+                        .zip(&powers)
+                        .map(|(&past, power)| power[past][next])
+                        .product()
+                })
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            let roll = rng.random_range(0.0..total);
+            let mut cumulative = 0.0;
+            let next = weights
+                .iter()
+                .position(|weight| {
+                    cumulative += weight;
+                    roll < cumulative
+                })
+                .unwrap_or_else(|| weights.iter().rposition(|&w| w > 0.0).unwrap());
+
+            history.push_back(next);
+            if history.len() > history_len {
+                history.pop_front();
+            }
+            path.push(history.iter().map(|&i| states[i]).collect());
+        }
+        path
+    }
+
+    /// Estimate the stationary distribution from the visit frequencies of a seeded walk.
+    ///
+    /// The start state is drawn uniformly from the registered states by an RNG seeded with
+    /// `seed`, which then drives a walk of `n_steps + burn_in` steps; the first `burn_in` states
+    /// are discarded so the estimate does not depend on where the walk started. In a chain with
+    /// several closed classes the estimate describes whichever class the walk falls into.
+    /// Returns an empty map if the chain is empty or the walk ends during burn-in.
+    pub fn sample_stationary_empirically(
+        &self,
+        n_steps: usize,
+        burn_in: usize,
+        seed: u64,
+    ) -> HashMap<T, f64> {
+        let steps: Vec<&ToStep<T>> = self.steps.values().collect();
+        if steps.is_empty() {
+            return HashMap::new();
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let start = steps[rng.random_range(0..steps.len())].clone();
+        Self::visit_frequencies(start, n_steps + burn_in, burn_in, &mut rng)
+    }
+
+    /// Export the chain as a map from every state to its outgoing `(target, weight)` pairs,
+    /// sorted by weight in descending order.
+    pub fn to_adjacency_list(&self) -> HashMap<T, Vec<(T, usize)>> {
+        self.steps
+            .iter()
+            .map(|(&state, step)| {
+                let mut targets: Vec<(T, usize)> = step
+                    .transitions
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|(to_step, &weight)| (to_step.state, weight))
+                    .collect();
+                targets.sort_by_key(|&(_, weight)| Reverse(weight));
+                (state, targets)
+            })
+            .collect()
+    }
+
+    /// Walk the chain for `steps` steps from every registered state.
+    ///
+    /// Equivalent to `walk_all_states(steps, seed)`.
+    pub fn walk_n_steps_from_each(&self, steps: usize, seed: u64) -> HashMap<T, Vec<T>> {
+        self.walk_all_states(steps, seed)
+    }
+
+    /// Sum every transition weight in the chain.
+    pub fn total_transition_weight(&self) -> usize {
+        self.steps
+            .values()
+            .map(|step| step.transitions.read().unwrap().values().sum::<usize>())
+            .sum()
+    }
+
+    /// Return the `(from, to, weight)` of the heaviest transition, or `None` if there are no
+    /// transitions. Ties are broken arbitrarily.
+    pub fn max_transition_weight(&self) -> Option<(T, T, usize)> {
+        self.edges()
+            .into_iter()
+            .max_by_key(|&(_, _, weight)| weight)
+    }
+
+    /// Build a histogram mapping each transition weight to the number of transitions with it.
+    pub fn weight_distribution(&self) -> HashMap<usize, usize> {
+        let mut histogram = HashMap::new();
+        for (_, _, weight) in self.edges() {
+            *histogram.entry(weight).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Return the mean number of outgoing transitions per state, or `0.0` for an empty chain.
+    pub fn average_out_degree(&self) -> f64 {
+        if self.state_count() == 0 {
+            return 0.0;
+        }
+        self.edge_count() as f64 / self.state_count() as f64
+    }
+
+    /// Return `(state, in_degree, out_degree)` for every state, sorted by total degree in
+    /// descending order.
+    ///
+    /// A self-loop counts towards both degrees.
+    pub fn degree_sequence(&self) -> Vec<(T, usize, usize)> {
+        let mut degrees: HashMap<T, (usize, usize)> =
+            self.states().map(|state| (state, (0, 0))).collect();
+        for (from, to, _) in self.edges() {
+            degrees.get_mut(&from).unwrap().1 += 1;
+            degrees.entry(to).or_default().0 += 1;
+        }
+        let mut sequence: Vec<(T, usize, usize)> = degrees
+            .into_iter()
+            .map(|(state, (in_degree, out_degree))| (state, in_degree, out_degree))
+            .collect();
+        sequence.sort_by_key(|&(_, in_degree, out_degree)| Reverse(in_degree + out_degree));
+        sequence
+    }
+
+    /// Return the `top_n` states through which the most shortest paths pass.
+    ///
+    /// States are ranked by betweenness centrality over unweighted shortest paths, computed with
+    /// Brandes' algorithm in `O(VE)`. Walks between other states have to funnel through these
+    /// states, so disrupting them slows mixing the most. Ties are broken arbitrarily.
+    pub fn find_bottleneck_states(&self, top_n: usize) -> Vec<T> {
+        let (states, index) = self.indexed_states();
+        let adjacency = self.adjacency();
+        let neighbours: Vec<Vec<usize>> = states
+            .iter()
+            .map(|state| adjacency[state].iter().map(|to| index[to]).collect())
+            .collect();
+
+        let n = states.len();
+        let mut centrality = vec![0.0; n];
+        for source in 0..n {
+            let mut order = Vec::with_capacity(n);
+            let mut predecessors = vec![Vec::new(); n];
+            let mut paths = vec![0.0; n];
+            let mut distance = vec![usize::MAX; n];
+            paths[source] = 1.0;
+            distance[source] = 0;
+            let mut queue = VecDeque::from([source]);
+            while let Some(v) = queue.pop_front() {
+                order.push(v);
+                for &w in &neighbours[v] {
+                    if distance[w] == usize::MAX {
+                        distance[w] = distance[v] + 1;
+                        queue.push_back(w);
+                    }
+                    if distance[w] == distance[v] + 1 {
+                        paths[w] += paths[v];
+                        predecessors[w].push(v);
+                    }
+                }
+            }
+            let mut dependency = vec![0.0; n];
+            for &w in order.iter().rev() {
+                for &v in &predecessors[w] {
+                    dependency[v] += paths[v] / paths[w] * (1.0 + dependency[w]);
+                }
+                if w != source {
+                    centrality[w] += dependency[w];
+                }
+            }
+        }
+
+        let mut ranked: Vec<(T, f64)> = states.into_iter().zip(centrality).collect();
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        ranked
+            .into_iter()
+            .take(top_n)
+            .map(|(state, _)| state)
+            .collect()
+    }
+
+    /// Extract the backbone of the chain as a maximum spanning tree over its transitions.
+    ///
+    /// Transitions are treated as undirected edges for connectivity and added heaviest first
+    /// with Kruskal's algorithm, which also maximizes the minimum weight on the tree. Each kept
+    /// transition retains its original direction and weight. A chain that is not weakly
+    /// connected yields a spanning forest, and self-loops and zero-weight transitions are never
+    /// kept.
+    pub fn max_weight_spanning_tree(&self) -> Chain<T> {
+        fn find(parents: &mut [usize], i: usize) -> usize {
+            let mut root = i;
+            while parents[root] != root {
+                root = parents[root];
+            }
+            let mut current = i;
+            while parents[current] != root {
+                let next = parents[current];
+                parents[current] = root;
+                current = next;
+            }
+            root
+        }
+
+        let (states, index) = self.indexed_states();
+        let mut parents: Vec<usize> = (0..states.len()).collect();
+        let mut edges = self.edges();
+        edges.retain(|&(_, _, weight)| weight > 0);
+        edges.sort_by_key(|&(_, _, weight)| Reverse(weight));
+
+        let mut tree = Chain::new();
+        for &state in &states {
+            tree.insert_state(state);
+        }
+        for (from, to, weight) in edges {
+            let (a, b) = (
+                find(&mut parents, index[&from]),
+                find(&mut parents, index[&to]),
+            );
+            if a != b {
+                parents[a] = b;
+                tree.insert_transition(from, to, weight);
+            }
+        }
+        tree
+    }
+
+    /// Estimate how strongly the stationary distribution reacts to each transition out of
+    /// `state`, keyed by the transition's target.
+    ///
+    /// For every target the transition probability is raised by `perturbation` (capped at
+    /// `1.0`), the other transitions of `state` are scaled down so the row still sums to `1.0`,
+    /// and the stationary distribution is recomputed. The value is the L1 change of the
+    /// stationary distribution divided by the applied change, a finite difference of its
+    /// derivative. Returns an empty map if `state` is not registered or the chain has no unique
+    /// stationary distribution.
+    pub fn sensitivity_analysis(&self, state: T, perturbation: f64) -> HashMap<T, f64> {
+        let (states, matrix) = self.transition_matrix();
+        let (Some(i), Some(base)) = (states.iter().position(|&s| s == state), stationary(&matrix))
+        else {
+            return HashMap::new();
+        };
+
+        let mut sensitivities = HashMap::new();
+        for (j, &probability) in matrix[i].iter().enumerate() {
+            if probability <= 0.0 {
+                continue;
+            }
+            let raised = (probability + perturbation).clamp(0.0, 1.0);
+            let delta = raised - probability;
+            if delta == 0.0 || probability >= 1.0 {
+                sensitivities.insert(states[j], 0.0);
+                continue;
+            }
+            let scale = (1.0 - raised) / (1.0 - probability);
+            let mut perturbed = matrix.clone();
+            for (k, value) in perturbed[i].iter_mut().enumerate() {
+                *value = if k == j { raised } else { *value * scale };
+            }
+            if let Some(shifted) = stationary(&perturbed) {
+                let change: f64 = shifted.iter().zip(&base).map(|(a, b)| (a - b).abs()).sum();
+                sensitivities.insert(states[j], change / delta.abs());
+            }
+        }
+        sensitivities
+    }
+
+    /// Simulate the discounted return `Σ γᵗ · reward(xₜ)` of a seeded walk of `n_steps`
+    /// states from `start`, treating the chain as a Markov reward process.
+    ///
+    /// States missing from `rewards` yield `0.0`, and a walk that reaches a state without
+    /// outgoing transitions stops collecting rewards. Returns `f64::NAN` if `start` is not
+    /// registered.
+    pub fn simulate_markov_reward_process(
+        &self,
+        rewards: &HashMap<T, f64>,
+        gamma: f64,
+        start: T,
+        n_steps: usize,
+        seed: u64,
+    ) -> f64 {
+        let Some(path) = self.walk(start, n_steps, seed) else {
+            return f64::NAN;
+        };
+        let mut discount = 1.0;
+        let mut total = 0.0;
+        for state in path {
+            total += discount * rewards.get(&state).copied().unwrap_or(0.0);
+            discount *= gamma;
+        }
+        total
+    }
+
+    /// Solve the Bellman equation `V(s) = reward(s) + γ · Σ P(s, s') · V(s')` for every state.
+    ///
+    /// The linear system `(I - γP)V = reward` is solved directly; states missing from `rewards`
+    /// yield `0.0`, and states without outgoing transitions are worth only their own reward.
+    /// Returns an empty map if the system is singular, which can happen for `gamma = 1.0`.
+    pub fn value_function(&self, rewards: &HashMap<T, f64>, gamma: f64) -> HashMap<T, f64> {
+        let (states, matrix) = self.transition_matrix();
+        let a = matrix
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(j, &p)| if i == j { 1.0 } else { 0.0 } - gamma * p)
+                    .collect()
+            })
+            .collect();
+        let b = states
+            .iter()
+            .map(|state| vec![rewards.get(state).copied().unwrap_or(0.0)])
+            .collect();
+        let Some(values) = solve(a, b) else {
+            return HashMap::new();
+        };
+        states
+            .into_iter()
+            .zip(values.into_iter().map(|row| row[0]))
+            .collect()
+    }
+
+    /// Walk the chain from `start` and reinforce every traversed transition, Hebbian style.
+    ///
+    /// After each step the traversed weight grows by `learning_rate` and every other transition
+    /// of the same source shrinks by `learning_rate / (out_degree - 1)`. The results are
+    /// rounded so the source's total weight stays exactly the same, and every transition that
+    /// had a positive weight keeps at least one unit so it can be reinforced again later. This
+    /// means updates much smaller than one weight unit are lost; scale the weights up first if
+    /// needed. Does nothing if `start` is not registered.
+    pub fn walk_and_update_weights(&self, start: T, steps: usize, learning_rate: f64, seed: u64) {
+        let Some(mut current) = self.state_to_step(start) else {
+            return;
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 1..steps {
+            let Some(next) = current.next_with_rng(&mut rng) else {
+                break;
+            };
+            {
+                let mut transitions = current.transitions.write().unwrap();
+                let total: usize = transitions.values().sum();
+                let others = transitions.len().saturating_sub(1).max(1) as f64;
+                // Positive transitions keep at least one unit so they can be reinforced again.
+                let targets: Vec<(ToStep<T>, f64, usize)> = transitions
+                    .iter()
+                    .map(|(to_step, &weight)| {
+                        let minimum = usize::from(weight > 0);
+                        let weight = weight as f64;
+                        let target = if *to_step == next {
+                            weight + learning_rate
+                        } else {
+                            weight - learning_rate / others
+                        };
+                        (to_step.clone(), target.max(minimum as f64), minimum)
+                    })
+                    .collect();
+
+                // Rescale to the original total, then round with the largest remainder method,
+                // breaking ties in favour of the traversed transition.
+                let target_total: f64 = targets.iter().map(|(_, target, _)| target).sum();
+                let mut shares: Vec<(ToStep<T>, usize, f64, usize)> = targets
+                    .into_iter()
+                    .map(|(to_step, target, minimum)| {
+                        let exact = target * total as f64 / target_total;
+                        (to_step, exact.floor() as usize, exact.fract(), minimum)
+                    })
+                    .collect();
+                let assigned: usize = shares.iter().map(|(_, floor, _, _)| floor).sum();
+                shares.sort_by(|a, b| b.2.total_cmp(&a.2).then((b.0 == next).cmp(&(a.0 == next))));
+                for share in shares.iter_mut().take(total.saturating_sub(assigned)) {
+                    share.1 += 1;
+                }
+                // Rescaling can push a clamped transition back below one unit; refill it from
+                // the heaviest transition.
+                while let Some(short) = shares.iter().position(|share| share.1 < share.3) {
+                    let heaviest = (0..shares.len()).max_by_key(|&k| shares[k].1).unwrap();
+                    shares[heaviest].1 -= 1;
+                    shares[short].1 += 1;
+                }
+                transitions.clear();
+                transitions.extend(
+                    shares
+                        .into_iter()
+                        .filter(|(_, weight, _, _)| *weight > 0)
+                        .map(|(to_step, weight, _, _)| (to_step, weight)),
+                );
+            }
+            current = next;
+        }
+    }
+
+    /// Replace the outgoing weights of every step with the counts of `n_samples` transitions
+    /// drawn with replacement from its current distribution, a bootstrap resample.
+    ///
+    /// Targets that are never drawn lose their transition. Steps without outgoing weight, and
+    /// every step when `n_samples` is zero, are left untouched.
+    pub fn resample_transitions(&self, n_samples: usize, rng: &mut impl Rng) {
+        if n_samples == 0 {
+            return;
+        }
+        for step in self.steps.values() {
+            #[allow(clippy::mutable_key_type)]
+            let mut counts: HashMap<ToStep<T>, usize> = HashMap::new();
+            for _ in 0..n_samples {
+                let Some(next) = step.next_with_rng(rng) else {
+                    break;
+                };
+                *counts.entry(next).or_insert(0) += 1;
+            }
+            if !counts.is_empty() {
+                *step.transitions.write().unwrap() = counts;
+            }
+        }
+    }
+
+    /// Estimate a 95% bootstrap confidence interval for every transition probability.
+    ///
+    /// Each of the `n_bootstrap` replicates redraws as many transitions from every step as its
+    /// total weight, treating the weights as observed counts, and recomputes the probabilities.
+    /// The interval spans the 2.5th to the 97.5th percentile of the replicates. Returns an empty
+    /// map if `n_bootstrap` is zero.
+    ///
+    /// A draw is a binary search over the step's cumulative weights, so the cost is
+    /// `O(n_bootstrap · Σ observations · log out_degree)`. Steps are capped at `10_000`
+    /// observations, which widens the intervals of steps with larger weights, such as those
+    /// produced by `power_iteration`.
+    pub fn estimate_confidence_intervals(
+        &self,
+        n_bootstrap: usize,
+        seed: u64,
+    ) -> HashMap<(T, T), (f64, f64)> {
+        if n_bootstrap == 0 {
+            return HashMap::new();
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut intervals = HashMap::new();
+        for (&from, step) in &self.steps {
+            let (targets, cumulative): (Vec<T>, Vec<usize>) = {
+                let transitions = step.transitions.read().unwrap();
+                let mut running = 0;
+                transitions
+                    .iter()
+                    .map(|(to_step, &weight)| {
+                        running += weight;
+                        (to_step.state, running)
+                    })
+                    .unzip()
+            };
+            let total = cumulative.last().copied().unwrap_or(0);
+            if total == 0 {
+                continue;
+            }
+            let observations = total.min(BOOTSTRAP_OBSERVATION_LIMIT);
+            let mut replicates = vec![Vec::with_capacity(n_bootstrap); targets.len()];
+            for _ in 0..n_bootstrap {
+                let mut counts = vec![0usize; targets.len()];
+                for _ in 0..observations {
+                    let roll = rng.random_range(0..total);
+                    counts[cumulative.partition_point(|&c| c <= roll)] += 1;
+                }
+                for (values, count) in replicates.iter_mut().zip(counts) {
+                    values.push(count as f64 / observations as f64);
+                }
+            }
+            for (&to, mut values) in targets.iter().zip(replicates) {
+                values.sort_by(f64::total_cmp);
+                let percentile = |q: f64| values[(q * (values.len() - 1) as f64).round() as usize];
+                intervals.insert((from, to), (percentile(0.025), percentile(0.975)));
+            }
+        }
+        intervals
+    }
+
+    /// Add or update a self-loop with `weight` on every state not in `states`.
+    ///
+    /// Self-loops make a chain lazy and therefore aperiodic; excluding states keeps, for
+    /// example, absorbing states without outgoing transitions. A zero `weight` adds nothing.
+    pub fn add_all_self_transitions_except(&mut self, states: &HashSet<T>, weight: usize) {
+        if weight == 0 {
+            return;
+        }
+        for step in self.steps.values() {
+            if !states.contains(&step.state) {
+                step.insert_transition(step.clone(), weight);
+            }
+        }
+    }
+
+    /// Walk the chain from every state in `starts` at once, driving all walkers with the same
+    /// random numbers.
+    ///
+    /// At each step a single value is drawn from an RNG seeded with `seed` and every walker
+    /// chooses its transition from it, so walkers that meet stay together from then on. A
+    /// walker stops when it reaches a state without outgoing transitions, and walkers starting
+    /// from an unregistered state get an empty path.
+    pub fn walk_synchronous(&self, starts: Vec<T>, steps: usize, seed: u64) -> Vec<Vec<T>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut walkers: Vec<Option<ToStep<T>>> = starts
+            .iter()
+            .map(|&start| self.state_to_step(start))
+            .collect();
+        let mut paths: Vec<Vec<T>> = walkers
+            .iter()
+            .map(|walker| walker.iter().map(|step| step.state).collect())
+            .collect();
+        for _ in 1..steps {
+            let shared: u64 = rng.random();
+            for (walker, path) in walkers.iter_mut().zip(&mut paths) {
+                *walker = walker
+                    .as_ref()
+                    .and_then(|step| step.next_with_rng(&mut StdRng::seed_from_u64(shared)));
+                if let Some(step) = walker {
+                    path.push(step.state);
+                }
+            }
+            if walkers.iter().all(Option::is_none) {
+                break;
+            }
+        }
+        paths
+    }
+
+    /// Estimate the expected number of steps before two synchronously coupled walkers, started
+    /// from the two states farthest apart, first occupy the same state.
+    ///
+    /// Each of the `n_simulations` runs drives both walkers with the same random numbers, as in
+    /// `walk_synchronous`, for at most `10_000` steps; a walker stuck in a state without
+    /// outgoing transitions stays there. The result is an empirical upper bound on the mixing
+    /// time. Returns `None` if the chain is empty, `n_simulations` is zero, or any run fails to
+    /// couple, since leaving such runs out would bias the mean downwards.
+    pub fn first_coupling_time(&self, n_simulations: usize, seed: u64) -> Option<f64> {
+        let (a, b) = self.farthest_pair()?;
+        let (a, b) = (self.steps[&a].clone(), self.steps[&b].clone());
+        if n_simulations == 0 {
+            return None;
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut total = 0;
+        for _ in 0..n_simulations {
+            total += Self::meeting_time(&a, &b, |x, y| {
+                let shared: u64 = rng.random();
+                (
+                    x.next_with_rng(&mut StdRng::seed_from_u64(shared))
+                        .unwrap_or(x.clone()),
+                    y.next_with_rng(&mut StdRng::seed_from_u64(shared))
+                        .unwrap_or(y.clone()),
+                )
+            })?;
+        }
+        Some(total as f64 / n_simulations as f64)
+    }
+}
@@ -0,0 +1,34 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+/// Errors returned by operations on a `Chain`.
+#[derive(Debug)]
+pub enum MarkovError {
+    /// A probability distribution does not sum to `1.0`; holds the actual sum.
+    InvalidDistribution(f64),
+    /// A probability is negative or not finite; holds the offending value.
+    InvalidProbability(f64),
+    /// A state is not registered in the chain; holds its `Debug` representation.
+    UnknownState(String),
+}
+
+impl Display for MarkovError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            MarkovError::InvalidDistribution(sum) => {
+                write!(f, "distribution sums to {sum}, expected 1.0")
+            }
+            MarkovError::InvalidProbability(probability) => {
+                write!(
+                    f,
+                    "invalid probability {probability}, expected a finite value >= 0.0"
+                )
+            }
+            MarkovError::UnknownState(state) => write!(f, "unknown state: {state}"),
+        }
+    }
+}
+
+impl Error for MarkovError {}
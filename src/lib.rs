@@ -5,7 +5,8 @@
 //! ## Features
 //! - Generic over state type `T` (must be `Eq + Copy + Hash + Debug`)
 //! - Weighted transitions between states
-//! - Deterministic and mutable walks
+//! - Deterministic, mutable and lazy walks
+//! - A `Chain` registry for building and walking chains by state value
 //!
 //! ## Examples
 //! ```rust
@@ -21,12 +22,21 @@
 //! let path = walk(step_false, 100);
 //! assert_eq!(path.len(), 100);
 //! ```
+mod chain;
+mod error;
+mod linalg;
 mod step;
-pub use step::{Step, ToStep, mut_walk, walk};
+pub use chain::Chain;
+pub use error::MarkovError;
+pub use step::{Step, ToStep, WalkIter, mut_walk, walk, walk_seeded};
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use rand::{SeedableRng, rngs::StdRng};
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    };
 
     use super::*;
 
@@ -82,4 +92,1034 @@ mod tests {
         assert!(path.contains(&true));
         assert_eq!(step_true_count + step_false_count, 103);
     }
+
+    #[test]
+    fn walk_from_distribution() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'b', 1);
+        chain.insert_transition('b', 'a', 1);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let init_dist = HashMap::from([('b', 1.0)]);
+        let path = chain
+            .walk_from_distribution(&init_dist, 4, &mut rng)
+            .unwrap();
+        assert_eq!(path, vec!['b', 'a', 'b', 'a']);
+
+        let unnormalized = HashMap::from([('a', 0.5), ('b', 0.4)]);
+        assert!(matches!(
+            chain.walk_from_distribution(&unnormalized, 4, &mut rng),
+            Err(MarkovError::InvalidDistribution(_))
+        ));
+        let not_a_number = HashMap::from([('a', f64::NAN)]);
+        assert!(matches!(
+            chain.walk_from_distribution(&not_a_number, 4, &mut rng),
+            Err(MarkovError::InvalidProbability(p)) if p.is_nan()
+        ));
+        let negative = HashMap::from([('a', 1.5), ('b', -0.5)]);
+        assert!(matches!(
+            chain.walk_from_distribution(&negative, 4, &mut rng),
+            Err(MarkovError::InvalidProbability(-0.5))
+        ));
+        let unknown = HashMap::from([('z', 1.0)]);
+        assert!(matches!(
+            chain.walk_from_distribution(&unknown, 4, &mut rng),
+            Err(MarkovError::UnknownState(_))
+        ));
+    }
+
+    #[test]
+    fn verify_row_stochastic() {
+        let mut chain = Chain::new();
+        chain.insert_transition(1, 2, 3);
+        chain.insert_transition(1, 1, 1);
+        chain.insert_transition(2, 1, 5);
+        assert!(chain.verify_row_stochastic(1e-9));
+
+        chain.insert_transition(2, 3, 1);
+        assert!(!chain.verify_row_stochastic(1e-9));
+    }
+
+    #[test]
+    fn star_chain() {
+        let chain = Chain::star_chain(0, &[1, 2, 3], 2, 5);
+        assert_eq!(chain.states().count(), 4);
+        let center = chain.state_to_step(0).unwrap();
+        let leaf = chain.state_to_step(1).unwrap();
+        assert_eq!(center.transitions.read().unwrap().len(), 3);
+        assert_eq!(center.transitions.read().unwrap()[&leaf], 5);
+        assert_eq!(leaf.transitions.read().unwrap()[&center], 2);
+    }
+
+    #[test]
+    fn path_chain() {
+        let chain = Chain::path_chain(&["a", "b", "c"]);
+        assert_eq!(
+            walk(chain.state_to_step("a").unwrap(), 10),
+            vec!["a", "b", "c"]
+        );
+        assert!(chain.state_to_step("c").unwrap().next().is_none());
+    }
+
+    #[test]
+    fn complete_bipartite_chain() {
+        let chain = Chain::complete_bipartite_chain(&[1, 2], &[10, 20, 30]);
+        assert_eq!(chain.states().count(), 5);
+        assert!(chain.verify_row_stochastic(1e-9));
+        let path = walk(chain.state_to_step(1).unwrap(), 50);
+        for pair in path.windows(2) {
+            assert_ne!(pair[0] < 10, pair[1] < 10);
+        }
+    }
+
+    #[test]
+    fn random_chain() {
+        let states: Vec<u32> = (0..20).collect();
+        let chain = Chain::random_chain(&states, 0.3, 42);
+        let edges: Vec<(u32, u32, usize)> = states
+            .iter()
+            .flat_map(|&from| {
+                let step = chain.state_to_step(from).unwrap();
+                let transitions = step.transitions.read().unwrap();
+                transitions
+                    .iter()
+                    .map(|(to, &weight)| (from, to.state, weight))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(chain.states().count(), 20);
+        assert!(!edges.is_empty() && edges.len() < 400);
+        assert!(
+            edges
+                .iter()
+                .all(|&(_, _, weight)| (1..=10).contains(&weight))
+        );
+
+        assert_eq!(
+            Chain::random_chain(&states, 1.0, 0)
+                .state_to_step(0)
+                .unwrap()
+                .transitions
+                .read()
+                .unwrap()
+                .len(),
+            20
+        );
+        assert!(
+            Chain::random_chain(&states, 0.0, 0)
+                .state_to_step(0)
+                .unwrap()
+                .next()
+                .is_none()
+        );
+        assert_eq!(Chain::random_chain(&states, f64::NAN, 0).edge_count(), 0);
+    }
+
+    #[test]
+    fn lazy_walk() {
+        let mut chain = Chain::new();
+        chain.insert_transition(true, false, 1);
+        chain.insert_transition(false, true, 3);
+        chain.insert_transition(false, false, 1);
+        assert_eq!(chain.lazy_walk(true).unwrap().take(100).count(), 100);
+        assert!(chain.lazy_walk(true).unwrap().nth(1) == Some(false));
+
+        let path = Chain::path_chain(&[1, 2, 3]);
+        assert_eq!(
+            path.lazy_walk(1).unwrap().collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(path.lazy_walk(4).is_none());
+    }
+
+    #[test]
+    fn chain_walk() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4], 1.0, 3);
+        let path = chain.walk(1, 50, 9).unwrap();
+        assert_eq!(path.len(), 50);
+        assert_eq!(path[0], 1);
+        assert_eq!(chain.walk(1, 50, 9), Some(path));
+        assert_eq!(chain.walk(5, 50, 9), None);
+    }
+
+    #[test]
+    fn chain_mut_walk() {
+        let mut chain = Chain::new();
+        chain.insert_transition(false, true, 1);
+        chain.insert_transition(true, false, 1);
+        let path = chain
+            .mut_walk(false, 10, |current, next| {
+                *current.transitions.write().unwrap().get_mut(&next).unwrap() += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            path,
+            vec![
+                false, true, false, true, false, true, false, true, false, true
+            ]
+        );
+        let forward = chain.state_to_step(false).unwrap();
+        assert_eq!(
+            forward.transitions.read().unwrap().values().sum::<usize>(),
+            6
+        );
+
+        assert!(matches!(
+            chain.mut_walk(false, 10, |_, next| Err(MarkovError::UnknownState(
+                format!("{:?}", next.state)
+            ))),
+            Err(MarkovError::UnknownState(_))
+        ));
+        let unknown = Chain::<bool>::new().mut_walk(false, 10, |_, _| Ok(()));
+        assert!(matches!(unknown, Err(MarkovError::UnknownState(_))));
+    }
+
+    #[test]
+    fn power_iteration() {
+        let chain = Chain::complete_bipartite_chain(&['a'], &['b']);
+        let two_step = chain.power_iteration(2);
+        let a = two_step.state_to_step('a').unwrap();
+        assert_eq!(a.next().unwrap().state, 'a');
+        assert_eq!(a.transitions.read().unwrap().len(), 1);
+
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 0, 1);
+        chain.insert_transition(0, 1, 1);
+        chain.insert_transition(1, 0, 1);
+        let (states, matrix) = chain.power_iteration(3).transition_matrix();
+        let i = states.iter().position(|&s| s == 0).unwrap();
+        let j = states.iter().position(|&s| s == 1).unwrap();
+        assert!((matrix[i][i] - 0.625).abs() < 1e-6);
+        assert!((matrix[i][j] - 0.375).abs() < 1e-6);
+
+        // Transitions to steps that were never registered are skipped rather than panicking.
+        let mut dangling = Chain::path_chain(&[1, 2]);
+        let two = dangling.state_to_step(2).unwrap();
+        two.insert_transition(Arc::new(Step::new(3)), 1);
+        two.insert_transition(two.clone(), 1);
+        let (states, matrix) = dangling.power_iteration(2).transition_matrix();
+        let i = states.iter().position(|&s| s == 1).unwrap();
+        let j = states.iter().position(|&s| s == 2).unwrap();
+        assert!((matrix[i][j] - 1.0).abs() < 1e-6);
+        assert_eq!(
+            dangling
+                .transition_count_matrix()
+                .1
+                .concat()
+                .iter()
+                .sum::<usize>(),
+            2
+        );
+        assert!(!dangling.is_strongly_connected());
+        assert_eq!(dangling.max_weight_spanning_tree().edge_count(), 1);
+        assert_eq!(dangling.deduplicate_by_transition_pattern(), 0);
+        dangling.stationary_distribution().unwrap();
+        dangling.transition_graph_summary();
+    }
+
+    #[test]
+    fn absorbing_probability() {
+        // Gambler's ruin on 0..=4 with a fair coin.
+        let mut chain = Chain::new();
+        for i in 1..4 {
+            chain.insert_transition(i, i - 1, 1);
+            chain.insert_transition(i, i + 1, 1);
+        }
+        chain.insert_transition(0, 0, 1);
+        assert!((chain.absorbing_probability(1, 4).unwrap() - 0.25).abs() < 1e-9);
+        assert!((chain.absorbing_probability(1, 0).unwrap() - 0.75).abs() < 1e-9);
+        assert_eq!(chain.absorbing_probability(0, 0), Some(1.0));
+        assert_eq!(chain.absorbing_probability(4, 0), Some(0.0));
+        assert_eq!(chain.absorbing_probability(1, 2), None);
+        assert_eq!(chain.absorbing_probability(9, 0), None);
+        assert_eq!(
+            Chain::complete_bipartite_chain(&[1], &[2]).absorbing_probability(1, 2),
+            None
+        );
+    }
+
+    #[test]
+    fn all_absorbing_probabilities() {
+        let mut chain = Chain::new();
+        chain.insert_transition("start", "left", 1);
+        chain.insert_transition("start", "right", 3);
+        chain.insert_transition("start", "loop", 4);
+        chain.insert_transition("loop", "trap", 1);
+        chain.insert_transition("trap", "loop", 1);
+        let probabilities = chain.all_absorbing_probabilities("start").unwrap();
+        assert_eq!(probabilities.len(), 2);
+        assert!((probabilities["left"] - 0.125).abs() < 1e-9);
+        assert!((probabilities["right"] - 0.375).abs() < 1e-9);
+        assert_eq!(
+            chain.all_absorbing_probabilities("loop").unwrap()["left"],
+            0.0
+        );
+        assert_eq!(
+            chain.all_absorbing_probabilities("left").unwrap()["left"],
+            1.0
+        );
+        assert!(chain.all_absorbing_probabilities("nowhere").is_none());
+    }
+
+    #[test]
+    fn sample_top_p_next() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'b', 6);
+        chain.insert_transition('a', 'c', 3);
+        chain.insert_transition('a', 'd', 1);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            assert_eq!(chain.sample_top_p_next('a', 0.5, &mut rng), Some('b'));
+            assert_ne!(chain.sample_top_p_next('a', 0.9, &mut rng), Some('d'));
+        }
+        assert_eq!(chain.sample_top_p_next('b', 0.9, &mut rng), None);
+        assert_eq!(chain.sample_top_p_next('z', 0.9, &mut rng), None);
+    }
+
+    #[test]
+    fn walk_top_p() {
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 1, 9);
+        chain.insert_transition(0, 0, 1);
+        chain.insert_transition(1, 0, 9);
+        chain.insert_transition(1, 1, 1);
+        let greedy = chain.walk_top_p(0, 20, 0.0, 5);
+        assert_eq!(greedy, (0..20).map(|i| i % 2).collect::<Vec<_>>());
+        assert_eq!(chain.walk_top_p(0, 20, 1.0, 5).len(), 20);
+        assert_eq!(
+            chain.walk_top_p(0, 20, 1.0, 5),
+            chain.walk_top_p(0, 20, 1.0, 5)
+        );
+        assert!(chain.walk_top_p(2, 20, 1.0, 5).is_empty());
+    }
+
+    #[test]
+    fn scale_all_weights() {
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 1, 10);
+        chain.insert_transition(0, 2, 1);
+        chain.scale_all_weights(0.25);
+        let zero = chain.state_to_step(0).unwrap();
+        let one = chain.state_to_step(1).unwrap();
+        assert_eq!(zero.transitions.read().unwrap().len(), 1);
+        assert_eq!(zero.transitions.read().unwrap()[&one], 3);
+    }
+
+    #[test]
+    fn transition_graph_summary() {
+        let summary = Chain::complete_bipartite_chain(&[1, 2], &[3, 4]).transition_graph_summary();
+        assert_eq!(
+            summary,
+            "states: 4\nedges: 8\nout-degree: avg 2.00, min 2, max 2\ndensity: 0.5000\n\
+             irreducible: true\naperiodic: false\nergodic: false"
+        );
+
+        let mut lazy = Chain::complete_bipartite_chain(&[1, 2], &[3, 4]);
+        lazy.insert_transition(1, 1, 1);
+        assert!(lazy.transition_graph_summary().ends_with("ergodic: true"));
+        let mut reducible = Chain::path_chain(&[1, 2]);
+        reducible.add_all_self_transitions_except(&HashSet::new(), 1);
+        assert!(
+            reducible
+                .transition_graph_summary()
+                .ends_with("irreducible: false\naperiodic: n/a\nergodic: false")
+        );
+    }
+
+    #[test]
+    fn stationary_distribution() {
+        let chain = Chain::star_chain(0, &[1, 2, 3], 1, 1);
+        let stationary = chain.stationary_distribution().unwrap();
+        assert!((stationary[&0] - 0.5).abs() < 1e-9);
+        assert!((stationary[&3] - 1.0 / 6.0).abs() < 1e-9);
+        let absorbed = Chain::path_chain(&[1, 2, 3])
+            .stationary_distribution()
+            .unwrap();
+        assert!(absorbed[&1].abs() < 1e-9);
+        assert!(absorbed[&2].abs() < 1e-9);
+        assert!((absorbed[&3] - 1.0).abs() < 1e-9);
+        let mut two_sinks = Chain::path_chain(&[1, 2]);
+        two_sinks.insert_transition(1, 3, 1);
+        assert!(two_sinks.stationary_distribution().is_none());
+        assert!(Chain::<u8>::new().stationary_distribution().is_none());
+    }
+
+    #[test]
+    fn simulate_and_compare_to_stationary() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4, 5], 1.0, 11);
+        let short = chain.simulate_and_compare_to_stationary(100, 3);
+        let long = chain.simulate_and_compare_to_stationary(100_000, 3);
+        assert!(long < short);
+        assert!(long < 0.02);
+        let absorbed = Chain::path_chain(&[1, 2, 3]).simulate_and_compare_to_stationary(10, 0);
+        assert!(absorbed.abs() < 1e-9);
+        assert!(
+            Chain::<u8>::new()
+                .simulate_and_compare_to_stationary(10, 0)
+                .is_nan()
+        );
+    }
+
+    #[test]
+    fn add_transitions_symmetric() {
+        let mut chain = Chain::new();
+        chain.add_transitions_symmetric(&[('a', 'b', 2), ('b', 'c', 5), ('c', 'c', 1)]);
+        let b = chain.state_to_step('b').unwrap();
+        let c = chain.state_to_step('c').unwrap();
+        assert_eq!(chain.edge_count(), 5);
+        assert_eq!(b.transitions.read().unwrap()[&c], 5);
+        assert_eq!(c.transitions.read().unwrap()[&b], 5);
+        assert_eq!(c.transitions.read().unwrap()[&c], 1);
+    }
+
+    #[test]
+    fn transition_count_matrix() {
+        let mut chain = Chain::new();
+        chain.insert_transition(1, 2, 4);
+        chain.insert_transition(2, 2, 7);
+        let (states, counts) = chain.transition_count_matrix();
+        assert_eq!(states, chain.transition_matrix().0);
+        let one = states.iter().position(|&s| s == 1).unwrap();
+        let two = states.iter().position(|&s| s == 2).unwrap();
+        assert_eq!(counts[one][two], 4);
+        assert_eq!(counts[two][two], 7);
+        assert_eq!(counts[two][one], 0);
+    }
+
+    #[test]
+    fn is_strongly_connected() {
+        assert!(Chain::star_chain(0, &[1, 2, 3], 1, 1).is_strongly_connected());
+        assert!(!Chain::path_chain(&[0, 1, 2]).is_strongly_connected());
+        assert!(!Chain::star_chain(0, &[1, 2, 3], 0, 1).is_strongly_connected());
+        assert!(!Chain::<u8>::new().is_strongly_connected());
+    }
+
+    #[test]
+    fn largest_scc() {
+        let mut chain = Chain::new();
+        chain.add_transitions_symmetric(&[(1, 2, 1), (2, 3, 1), (3, 1, 1)]);
+        chain.add_transitions_symmetric(&[(4, 5, 1)]);
+        chain.insert_transition(3, 4, 1);
+        chain.insert_transition(0, 1, 1);
+        let scc = chain.largest_scc();
+        let mut states: Vec<_> = scc.states().collect();
+        states.sort();
+        assert_eq!(states, vec![1, 2, 3]);
+        assert_eq!(scc.edge_count(), 6);
+        assert!(scc.is_strongly_connected());
+        assert_eq!(Chain::<u8>::new().largest_scc().state_count(), 0);
+    }
+
+    #[test]
+    fn normalize_all_weights() {
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 1, 1);
+        chain.insert_transition(0, 2, 1);
+        chain.insert_transition(0, 3, 1);
+        chain.insert_transition(1, 0, 7);
+        chain.insert_transition(2, 0, 1);
+        chain.insert_transition(2, 1, 1000);
+        chain.normalize_all_weights(100);
+        for state in [0, 1, 2] {
+            let step = chain.state_to_step(state).unwrap();
+            assert_eq!(
+                step.transitions.read().unwrap().values().sum::<usize>(),
+                100
+            );
+        }
+        let two = chain.state_to_step(2).unwrap();
+        assert_eq!(two.transitions.read().unwrap().len(), 1);
+        assert!(chain.state_to_step(3).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn log_probability_of_sequence() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'b', 1);
+        chain.insert_transition('a', 'a', 3);
+        chain.insert_transition('b', 'a', 1);
+        let expected = (0.75f64 * 0.25 * 1.0).ln();
+        assert!(
+            (chain.log_probability_of_sequence(&['a', 'a', 'b', 'a']) - expected).abs() < 1e-12
+        );
+        assert_eq!(chain.log_probability_of_sequence(&['a']), 0.0);
+        assert_eq!(
+            chain.log_probability_of_sequence(&['b', 'b']),
+            f64::NEG_INFINITY
+        );
+    }
+
+    #[test]
+    fn sample_path_proportional_to_probability() {
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 1, 1);
+        chain.insert_transition(0, 2, 99);
+        chain.insert_transition(1, 0, 1);
+        chain.insert_transition(2, 0, 1);
+        let path = chain.sample_path_proportional_to_probability(0, 5, 20, 8);
+        assert_eq!(path.len(), 5);
+        assert!(chain.log_probability_of_sequence(&path).is_finite());
+        assert_eq!(
+            path,
+            chain.sample_path_proportional_to_probability(0, 5, 20, 8)
+        );
+        assert!(
+            chain
+                .sample_path_proportional_to_probability(0, 5, 0, 8)
+                .is_empty()
+        );
+        assert!(
+            chain
+                .sample_path_proportional_to_probability(3, 5, 20, 8)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn verify_transition_weights_positive() {
+        let mut chain = Chain::new();
+        chain.insert_transition(0, 1, 2);
+        assert!(chain.verify_transition_weights_positive().is_empty());
+        chain.insert_transition(1, 0, 0);
+        assert_eq!(chain.verify_transition_weights_positive(), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn deduplicate_by_transition_pattern() {
+        let mut chain = Chain::new();
+        chain.insert_transition("start", "x", 1);
+        chain.insert_transition("start", "y", 1);
+        chain.insert_transition("start", "end", 1);
+        chain.insert_transition("x", "end", 2);
+        chain.insert_transition("y", "end", 5);
+        chain.insert_transition("x2", "x", 1);
+        chain.insert_transition("y2", "y", 3);
+        assert_eq!(chain.deduplicate_by_transition_pattern(), 2);
+        assert_eq!(chain.state_count(), 4);
+        assert_eq!(chain.edge_count(), 4);
+        let start = chain.state_to_step("start").unwrap();
+        let mut weights: Vec<usize> = start
+            .transitions
+            .read()
+            .unwrap()
+            .values()
+            .copied()
+            .collect();
+        weights.sort();
+        assert_eq!(weights, vec![1, 2]);
+        assert!(chain.state_to_step("end").is_some());
+        assert_eq!(chain.deduplicate_by_transition_pattern(), 0);
+
+        let mut zero = Chain::new();
+        zero.insert_transition(1, 9, 1);
+        zero.insert_transition(2, 9, 1);
+        zero.insert_transition(2, 8, 0);
+        assert_eq!(zero.deduplicate_by_transition_pattern(), 1);
+    }
+
+    #[test]
+    fn walk_all_states() {
+        let chain = Chain::random_chain(&[1, 2, 3], 1.0, 4);
+        let walks = chain.walk_all_states(10, 2);
+        assert_eq!(walks.len(), 3);
+        assert!(
+            walks
+                .iter()
+                .all(|(start, path)| path[0] == *start && path.len() == 10)
+        );
+        assert_eq!(walks, chain.walk_all_states(10, 2));
+    }
+
+    #[test]
+    fn connected_subchain_containing() {
+        let mut chain = Chain::path_chain(&[1, 2, 3]);
+        chain.insert_transition(0, 2, 1);
+        chain.insert_transition(7, 8, 1);
+        let component = chain.connected_subchain_containing(3).unwrap();
+        let mut states: Vec<_> = component.states().collect();
+        states.sort();
+        assert_eq!(states, vec![0, 1, 2, 3]);
+        assert_eq!(component.edge_count(), 3);
+        assert_eq!(
+            chain
+                .connected_subchain_containing(8)
+                .unwrap()
+                .state_count(),
+            2
+        );
+        assert!(chain.connected_subchain_containing(9).is_none());
+    }
+
+    #[test]
+    fn edges_between() {
+        let chain = Chain::star_chain('c', &['a', 'b'], 2, 7);
+        assert_eq!(chain.edges_between('c', 'a'), Some(7));
+        assert_eq!(chain.edges_between('b', 'c'), Some(2));
+        assert_eq!(chain.edges_between('a', 'b'), None);
+        assert_eq!(chain.edges_between('z', 'c'), None);
+    }
+
+    #[test]
+    fn from_corpus_with_order() {
+        let words: Vec<&str> = "a rose is a rose is a rose".split_whitespace().collect();
+        let unigrams = Chain::from_corpus_with_order(&words, 1);
+        assert_eq!(unigrams.state_count(), 3);
+        assert_eq!(unigrams.edges_between(&["a"], &["rose"]), Some(3));
+
+        let bigrams = Chain::from_corpus_with_order(&words, 2);
+        assert_eq!(bigrams.state_count(), 3);
+        assert_eq!(bigrams.edges_between(&["is", "a"], &["a", "rose"]), Some(2));
+        let path = bigrams.walk(&["a", "rose"], 3, 0).unwrap();
+        assert_eq!(path[1], &["rose", "is"]);
+
+        assert_eq!(Chain::from_corpus_with_order(&words, 0).state_count(), 0);
+        assert_eq!(Chain::from_corpus_with_order(&words, 9).state_count(), 0);
+    }
+
+    #[test]
+    fn mutual_coupling_time() {
+        let lazy = Chain::random_chain(&[1, 2, 3, 4], 1.0, 6);
+        let strict = lazy.mutual_coupling_time(0.01, 3).unwrap();
+        let loose = lazy.mutual_coupling_time(0.5, 3).unwrap();
+        assert!(loose <= strict);
+        assert!(strict > 0);
+        assert_eq!(
+            Chain::path_chain(&[1]).mutual_coupling_time(0.1, 0),
+            Some(0)
+        );
+        assert_eq!(Chain::<u8>::new().mutual_coupling_time(0.1, 0), None);
+    }
+
+    #[test]
+    fn chi_squared_goodness_of_fit() {
+        let chain = Chain::star_chain(0, &[1, 2], 1, 1);
+        let exact = HashMap::from([(0, 0.5), (1, 0.25), (2, 0.25)]);
+        assert!(chain.chi_squared_goodness_of_fit(&exact, 100).abs() < 1e-9);
+        let skewed = HashMap::from([(0, 0.6), (1, 0.2), (2, 0.2)]);
+        assert!((chain.chi_squared_goodness_of_fit(&skewed, 100) - 4.0).abs() < 1e-9);
+        let unknown = HashMap::from([(0, 0.5), (7, 0.5)]);
+        assert_eq!(
+            chain.chi_squared_goodness_of_fit(&unknown, 100),
+            f64::INFINITY
+        );
+
+        let absorbed = Chain::path_chain(&[1, 2, 3]);
+        let settled = HashMap::from([(3, 1.0)]);
+        assert!(absorbed.chi_squared_goodness_of_fit(&settled, 100).abs() < 1e-9);
+        let transient = HashMap::from([(2, 0.5), (3, 0.5)]);
+        assert_eq!(
+            absorbed.chi_squared_goodness_of_fit(&transient, 100),
+            f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn prune_states_below_stationary() {
+        let mut chain = Chain::star_chain(0, &[1, 2], 1, 9);
+        chain.insert_transition(0, 3, 1);
+        chain.insert_transition(3, 0, 1);
+        assert_eq!(chain.prune_states_below_stationary(0.1), 1);
+        assert!(chain.state_to_step(3).is_none());
+        assert_eq!(chain.edges_between(0, 1), Some(9));
+        assert_eq!(chain.edge_count(), 4);
+
+        let mut absorbed = Chain::path_chain(&[1, 2, 3]);
+        assert_eq!(absorbed.prune_states_below_stationary(0.1), 2);
+        assert_eq!(absorbed.states().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn add_initial_distribution_state() {
+        let mut chain = Chain::complete_bipartite_chain(&["a"], &["b"]);
+        chain.add_initial_distribution_state(
+            "init",
+            &HashMap::from([("a", 3), ("b", 1), ("c", 0), ("init", 2)]),
+        );
+        assert_eq!(chain.edges_between("init", "a"), Some(3));
+        assert_eq!(chain.edges_between("init", "b"), Some(1));
+        assert!(chain.state_to_step("c").is_none());
+        assert_eq!(chain.edges_between("init", "init"), None);
+        let path = chain.walk("init", 20, 1).unwrap();
+        assert_eq!(path.iter().filter(|&&s| s == "init").count(), 1);
+    }
+
+    #[test]
+    fn absorbing_probability_matrix() {
+        let (transient, absorbing, b) = Chain::path_chain(&[1, 2, 3])
+            .absorbing_probability_matrix()
+            .unwrap();
+        assert_eq!(absorbing, vec![3]);
+        assert_eq!(transient.len(), 2);
+        assert!(b.iter().all(|row| (row[0] - 1.0).abs() < 1e-12));
+        assert!(
+            Chain::star_chain(0, &[1], 1, 1)
+                .absorbing_probability_matrix()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn walk_with_history() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4], 0.6, 12);
+        let start = chain.states().next().unwrap();
+        let windows = chain.walk_with_history(start, 30, 3, 4);
+        assert!(!windows.is_empty());
+        assert_eq!(windows[0], vec![start]);
+        for (i, window) in windows.iter().enumerate() {
+            assert_eq!(window.len(), (i + 1).min(3));
+            assert!(chain.log_probability_of_sequence(window).is_finite());
+        }
+        assert_eq!(windows, chain.walk_with_history(start, 30, 3, 4));
+
+        let path = Chain::path_chain(&['a', 'b', 'c']);
+        assert_eq!(
+            path.walk_with_history('a', 10, 2, 0),
+            vec![vec!['a'], vec!['a', 'b'], vec!['b', 'c']]
+        );
+        assert_eq!(
+            path.walk_with_history('a', 10, 0, 0),
+            vec![vec!['a'], vec!['b'], vec!['c']]
+        );
+        assert!(path.walk_with_history('z', 10, 2, 0).is_empty());
+    }
+
+    #[test]
+    fn transition_similarity() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'x', 1);
+        chain.insert_transition('a', 'y', 1);
+        chain.insert_transition('b', 'x', 4);
+        chain.insert_transition('b', 'y', 4);
+        chain.insert_transition('c', 'x', 1);
+        chain.insert_transition('d', 'z', 1);
+        let step = |state| chain.state_to_step(state).unwrap();
+        assert!((step('a').transition_similarity(&step('b')) - 1.0).abs() < 1e-12);
+        assert!((step('a').transition_similarity(&step('c')) - 0.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(step('a').transition_similarity(&step('d')), 0.0);
+        assert_eq!(step('a').transition_similarity(&step('x')), 0.0);
+    }
+
+    #[test]
+    fn sample_stationary_empirically() {
+        let chain = Chain::star_chain(0, &[1, 2, 3], 1, 1);
+        let frequencies = chain.sample_stationary_empirically(20_000, 100, 5);
+        assert!((frequencies.values().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!((frequencies[&0] - 0.5).abs() < 1e-3);
+        assert!((frequencies[&1] - 1.0 / 6.0).abs() < 0.02);
+
+        let absorbed = Chain::path_chain(&[1, 2, 3]).sample_stationary_empirically(10, 5, 0);
+        assert!(absorbed.is_empty());
+
+        // The seeded start state decides which closed class the walk settles in.
+        let mut split = Chain::path_chain(&[0, 1]);
+        split.insert_transition(1, 1, 1);
+        split.add_transitions_symmetric(&[(2, 3, 1)]);
+        let settled = split.sample_stationary_empirically(100, 10, 0);
+        assert!(
+            settled == HashMap::from([(1, 1.0)]) || settled == HashMap::from([(2, 0.5), (3, 0.5)])
+        );
+        assert_eq!(split.sample_stationary_empirically(100, 10, 0), settled);
+        assert!(
+            Chain::<u8>::new()
+                .sample_stationary_empirically(100, 10, 0)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn to_adjacency_list() {
+        let mut chain = Chain::star_chain('c', &['a', 'b'], 1, 2);
+        chain.insert_transition('c', 'd', 5);
+        let adjacency = chain.to_adjacency_list();
+        assert_eq!(adjacency.len(), 4);
+        assert_eq!(adjacency[&'c'][0], ('d', 5));
+        assert_eq!(adjacency[&'c'].len(), 3);
+        assert_eq!(adjacency[&'a'], vec![('c', 1)]);
+        assert!(adjacency[&'d'].is_empty());
+    }
+
+    #[test]
+    fn from_adjacency_list() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4, 5], 0.5, 21);
+        let adjacency = chain.to_adjacency_list();
+        let rebuilt = Chain::from_adjacency_list(adjacency.clone());
+        assert_eq!(rebuilt.state_count(), 5);
+        assert_eq!(rebuilt.edge_count(), chain.edge_count());
+        for (state, mut targets) in rebuilt.to_adjacency_list() {
+            let mut expected = adjacency[&state].clone();
+            targets.sort();
+            expected.sort();
+            assert_eq!(targets, expected);
+        }
+    }
+
+    #[test]
+    fn walk_n_steps_from_each() {
+        let chain = Chain::star_chain(0, &[1, 2], 1, 1);
+        let walks = chain.walk_n_steps_from_each(5, 3);
+        assert_eq!(walks, chain.walk_all_states(5, 3));
+        assert_eq!(walks[&1][..2], [1, 0]);
+    }
+
+    #[test]
+    fn total_transition_weight() {
+        assert_eq!(
+            Chain::star_chain(0, &[1, 2, 3], 2, 5).total_transition_weight(),
+            21
+        );
+        assert_eq!(Chain::<u8>::new().total_transition_weight(), 0);
+    }
+
+    #[test]
+    fn max_transition_weight() {
+        let mut chain = Chain::star_chain(0, &[1, 2, 3], 2, 5);
+        chain.insert_transition(2, 3, 40);
+        assert_eq!(chain.max_transition_weight(), Some((2, 3, 40)));
+        assert_eq!(Chain::<u8>::path_chain(&[1]).max_transition_weight(), None);
+    }
+
+    #[test]
+    fn weight_distribution() {
+        let chain = Chain::star_chain(0, &[1, 2, 3], 2, 5);
+        assert_eq!(chain.weight_distribution(), HashMap::from([(2, 3), (5, 3)]));
+        assert!(Chain::<u8>::new().weight_distribution().is_empty());
+    }
+
+    #[test]
+    fn average_out_degree() {
+        assert_eq!(
+            Chain::star_chain(0, &[1, 2, 3], 2, 5).average_out_degree(),
+            1.5
+        );
+        assert_eq!(Chain::<u8>::new().average_out_degree(), 0.0);
+    }
+
+    #[test]
+    fn degree_sequence() {
+        let mut chain = Chain::star_chain(0, &[1, 2, 3], 1, 1);
+        chain.insert_transition(3, 3, 1);
+        let sequence = chain.degree_sequence();
+        assert_eq!(sequence[0], (0, 3, 3));
+        assert_eq!(sequence[1], (3, 2, 2));
+        assert!(sequence[2..].iter().all(|&(_, i, o)| i == 1 && o == 1));
+    }
+
+    #[test]
+    fn find_bottleneck_states() {
+        // Two triangles joined through a single bridge state.
+        let mut chain = Chain::new();
+        chain.add_transitions_symmetric(&[(1, 2, 1), (2, 3, 1), (3, 1, 1)]);
+        chain.add_transitions_symmetric(&[(5, 6, 1), (6, 7, 1), (7, 5, 1)]);
+        chain.add_transitions_symmetric(&[(3, 4, 1), (4, 5, 1)]);
+        assert_eq!(chain.find_bottleneck_states(1), vec![4]);
+        let mut top = chain.find_bottleneck_states(3);
+        top.sort();
+        assert_eq!(top, vec![3, 4, 5]);
+        assert_eq!(chain.find_bottleneck_states(100).len(), 7);
+    }
+
+    #[test]
+    fn max_weight_spanning_tree() {
+        let mut chain = Chain::new();
+        chain.add_transitions_symmetric(&[(1, 2, 5), (2, 3, 1), (3, 4, 7), (1, 3, 4), (2, 4, 2)]);
+        chain.insert_transition(1, 1, 9);
+        chain.insert_transition(5, 6, 1);
+        chain.insert_transition(7, 8, 0);
+        let tree = chain.max_weight_spanning_tree();
+        assert_eq!(tree.state_count(), 8);
+        assert_eq!(tree.edge_count(), 4);
+        let mut weights: Vec<usize> = tree.weight_distribution().into_keys().collect();
+        weights.sort();
+        assert_eq!(weights, vec![1, 4, 5, 7]);
+        assert!(tree.connected_subchain_containing(1).unwrap().state_count() == 4);
+    }
+
+    #[test]
+    fn sensitivity_analysis() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'a', 1);
+        chain.insert_transition('a', 'b', 1);
+        chain.insert_transition('b', 'a', 1);
+        chain.insert_transition('b', 'b', 1);
+        // Raising P(a, b) by δ gives π(a) = 0.5 / (1 + δ), and π(b) absorbs the difference,
+        // so |dπ|₁/dδ → 1.0.
+        let sensitivities = chain.sensitivity_analysis('a', 1e-6);
+        assert_eq!(sensitivities.len(), 2);
+        assert!((sensitivities[&'b'] - 1.0).abs() < 1e-4);
+        assert!((sensitivities[&'a'] - 1.0).abs() < 1e-4);
+        assert!(chain.sensitivity_analysis('z', 1e-6).is_empty());
+
+        // Every walk ends in the sink 'c', so transitions out of 'a' leave π unchanged.
+        let mut absorbed = Chain::path_chain(&['a', 'b', 'c']);
+        absorbed.insert_transition('a', 'c', 1);
+        let settled = absorbed.sensitivity_analysis('a', 1e-3);
+        assert_eq!(settled.len(), 2);
+        assert!(
+            settled
+                .values()
+                .all(|&sensitivity| sensitivity.abs() < 1e-6)
+        );
+    }
+
+    #[test]
+    fn simulate_markov_reward_process() {
+        let chain = Chain::complete_bipartite_chain(&['a'], &['b']);
+        let rewards = HashMap::from([('a', 1.0), ('b', 2.0)]);
+        let total = chain.simulate_markov_reward_process(&rewards, 0.5, 'a', 4, 0);
+        assert!((total - (1.0 + 0.5 * 2.0 + 0.25 * 1.0 + 0.125 * 2.0)).abs() < 1e-12);
+        assert!(
+            chain
+                .simulate_markov_reward_process(&rewards, 0.5, 'z', 4, 0)
+                .is_nan()
+        );
+    }
+
+    #[test]
+    fn value_function() {
+        let chain = Chain::complete_bipartite_chain(&['a'], &['b']);
+        let rewards = HashMap::from([('a', 1.0)]);
+        let values = chain.value_function(&rewards, 0.5);
+        // V(a) = 1 + 0.5 V(b), V(b) = 0.5 V(a).
+        assert!((values[&'a'] - 4.0 / 3.0).abs() < 1e-12);
+        assert!((values[&'b'] - 2.0 / 3.0).abs() < 1e-12);
+        assert!(chain.value_function(&rewards, 1.0).is_empty());
+
+        let path = Chain::path_chain(&[1, 2]);
+        let values = path.value_function(&HashMap::from([(2, 3.0)]), 0.9);
+        assert!((values[&1] - 2.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn walk_and_update_weights() {
+        let mut chain = Chain::new();
+        for to in 0..4 {
+            chain.insert_transition(0, to, 100);
+        }
+        for from in 1..4 {
+            chain.insert_transition(from, 0, 10);
+        }
+        chain.walk_and_update_weights(0, 200, 6.0, 13);
+        let zero = chain.state_to_step(0).unwrap();
+        let weights: Vec<usize> = zero.transitions.read().unwrap().values().copied().collect();
+        assert_eq!(weights.iter().sum::<usize>(), 400);
+        assert!(weights.iter().any(|&weight| weight != 100));
+        assert_eq!(chain.edges_between(1, 0), Some(10));
+
+        chain.walk_and_update_weights(9, 200, 6.0, 13);
+        assert_eq!(chain.state_count(), 4);
+
+        let mut pair = Chain::path_chain(&[0, 1]);
+        pair.insert_transition(0, 2, 1);
+        pair.insert_transition(1, 0, 1);
+        pair.insert_transition(2, 0, 1);
+        pair.walk_and_update_weights(0, 20, 0.5, 1);
+        assert_eq!(pair.edges_between(0, 1), Some(1));
+        assert_eq!(pair.edges_between(0, 2), Some(1));
+
+        // Whole-unit updates are applied exactly: the traversed branch gains what the others lose.
+        for (weight, targets, learning_rate, expected) in
+            [(2, 2, 1.0, vec![1, 3]), (2, 3, 2.0, vec![1, 1, 4])]
+        {
+            for seed in 0..50 {
+                let chain = Chain::star_chain(0, &(1..=targets).collect::<Vec<_>>(), 1, weight);
+                chain.walk_and_update_weights(0, 2, learning_rate, seed);
+                let center = chain.state_to_step(0).unwrap();
+                let mut weights: Vec<usize> = center
+                    .transitions
+                    .read()
+                    .unwrap()
+                    .values()
+                    .copied()
+                    .collect();
+                weights.sort();
+                assert_eq!(weights, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn resample_transitions() {
+        let mut chain = Chain::star_chain(0, &[1, 2, 3], 1, 5);
+        chain.insert_state(4);
+        chain.resample_transitions(50, &mut StdRng::seed_from_u64(2));
+        let center = chain.state_to_step(0).unwrap();
+        assert_eq!(
+            center.transitions.read().unwrap().values().sum::<usize>(),
+            50
+        );
+        assert_eq!(chain.edges_between(1, 0), Some(50));
+        assert!(chain.state_to_step(4).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn estimate_confidence_intervals() {
+        let mut chain = Chain::new();
+        chain.insert_transition('a', 'b', 30);
+        chain.insert_transition('a', 'c', 70);
+        chain.insert_transition('b', 'a', 1);
+        let intervals = chain.estimate_confidence_intervals(200, 17);
+        assert_eq!(intervals.len(), 3);
+        let (low, high) = intervals[&('a', 'c')];
+        assert!(low < 0.7 && 0.7 < high);
+        assert!(high - low < 0.3);
+        assert_eq!(intervals[&('b', 'a')], (1.0, 1.0));
+        assert!(chain.estimate_confidence_intervals(0, 17).is_empty());
+
+        // Weights around 1e6 are capped rather than redrawn one by one.
+        let scaled = Chain::random_chain(&(0..8).collect::<Vec<_>>(), 1.0, 3).power_iteration(2);
+        let (states, matrix) = scaled.transition_matrix();
+        let intervals = scaled.estimate_confidence_intervals(5, 1);
+        assert_eq!(intervals.len(), scaled.edge_count());
+        let (low, high) = intervals[&(states[0], states[1])];
+        assert!(low <= matrix[0][1] + 0.05 && matrix[0][1] - 0.05 <= high);
+    }
+
+    #[test]
+    fn add_all_self_transitions_except() {
+        let mut chain = Chain::complete_bipartite_chain(&[1, 2], &[3]);
+        chain.insert_transition(3, 4, 1);
+        chain.add_all_self_transitions_except(&HashSet::from([4]), 2);
+        assert_eq!(chain.edges_between(1, 1), Some(2));
+        assert_eq!(chain.edges_between(3, 3), Some(2));
+        assert_eq!(chain.edges_between(4, 4), None);
+
+        let mut unweighted = Chain::path_chain(&[1, 2]);
+        unweighted.add_all_self_transitions_except(&HashSet::new(), 0);
+        assert_eq!(unweighted.edge_count(), 1);
+        assert!(unweighted.verify_transition_weights_positive().is_empty());
+    }
+
+    #[test]
+    fn walk_synchronous() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4, 5], 1.0, 8);
+        let paths = chain.walk_synchronous(vec![1, 1, 2, 9], 40, 6);
+        assert_eq!(paths.len(), 4);
+        assert_eq!(paths[0], paths[1]);
+        assert_eq!(paths[2].len(), 40);
+        assert!(paths[3].is_empty());
+        let met = (0..40).find(|&t| paths[0][t] == paths[2][t]);
+        if let Some(t) = met {
+            assert_eq!(paths[0][t..], paths[2][t..]);
+        }
+    }
+
+    #[test]
+    fn first_coupling_time() {
+        let chain = Chain::random_chain(&[1, 2, 3, 4, 5], 1.0, 8);
+        let expected = chain.first_coupling_time(200, 1).unwrap();
+        assert!(expected >= 1.0);
+        assert_eq!(chain.first_coupling_time(200, 1), Some(expected));
+
+        let absorbing = Chain::path_chain(&[1, 2, 3]);
+        assert_eq!(absorbing.first_coupling_time(10, 0), Some(2.0));
+        assert_eq!(Chain::<u8>::new().first_coupling_time(10, 0), None);
+        assert_eq!(chain.first_coupling_time(0, 0), None);
+
+        // A walker that falls into the other sink never meets its partner, so about half of
+        // the runs fail and the estimate is undefined rather than biased.
+        let mut split = Chain::path_chain(&[1, 2]);
+        split.insert_transition(1, 3, 1);
+        assert_eq!(split.first_coupling_time(100, 0), None);
+    }
 }
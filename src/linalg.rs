@@ -0,0 +1,96 @@
+/// Multiply two square matrices of the same dimension.
+pub(crate) fn mat_mul(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = a.len();
+    let mut product = vec![vec![0.0; n]; n];
+    for (i, row) in a.iter().enumerate() {
+        for (k, &a_ik) in row.iter().enumerate() {
+            if a_ik == 0.0 {
+                continue;
+            }
+            for (j, &b_kj) in b[k].iter().enumerate() {
+                product[i][j] += a_ik * b_kj;
+            }
+        }
+    }
+    product
+}
+
+/// Create an identity matrix of dimension `n`.
+pub(crate) fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+/// Solve `a * x = b` for `x` by Gaussian elimination with partial pivoting.
+///
+/// `b` may hold several right-hand sides as columns. Returns `None` if `a` is singular.
+pub(crate) fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (pivot_a, pivot_b) = (a[col].clone(), b[col].clone());
+        for (row, (a_row, b_row)) in a.iter_mut().zip(b.iter_mut()).enumerate() {
+            let factor = a_row[col] / pivot_a[col];
+            if row == col || factor == 0.0 {
+                continue;
+            }
+            for (value, pivot_value) in a_row.iter_mut().zip(&pivot_a).skip(col) {
+                *value -= factor * pivot_value;
+            }
+            for (value, pivot_value) in b_row.iter_mut().zip(&pivot_b) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    for (i, row) in b.iter_mut().enumerate() {
+        for value in row.iter_mut() {
+            *value /= a[i][i];
+        }
+    }
+    Some(b)
+}
+
+/// Solve for the distribution `π` with `π = πP` and `Σπ = 1`.
+///
+/// Rows of zeros are treated as self-loops, so a state without outgoing transitions is
+/// absorbing. Returns `None` if `p` is empty or the distribution is not unique.
+pub(crate) fn stationary(p: &[Vec<f64>]) -> Option<Vec<f64>> {
+    let n = p.len();
+    if n == 0 {
+        return None;
+    }
+    let p: Vec<Vec<f64>> = p
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut row = row.clone();
+            if row.iter().all(|&value| value == 0.0) {
+                row[i] = 1.0;
+            }
+            row
+        })
+        .collect();
+    // Solve (Pᵀ - I)π = 0, replacing the last equation with Σπ = 1.
+    let mut a: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| p[j][i] - if i == j { 1.0 } else { 0.0 })
+                .collect()
+        })
+        .collect();
+    a[n - 1] = vec![1.0; n];
+    let mut b = vec![vec![0.0]; n];
+    b[n - 1][0] = 1.0;
+    let pi: Vec<f64> = solve(a, b)?.into_iter().map(|row| row[0]).collect();
+    // A near-singular system can still yield a solution, so check that it is a fixed point.
+    let residual: f64 = (0..n)
+        .map(|j| ((0..n).map(|i| pi[i] * p[i][j]).sum::<f64>() - pi[j]).abs())
+        .sum();
+    (residual < 1e-9).then_some(pi)
+}
@@ -1,5 +1,6 @@
 use rand::prelude::*;
 use std::{
+    cmp::Reverse,
     collections::HashMap,
     error::Error,
     fmt::{Debug, Formatter, Result as FmtResult},
@@ -79,9 +80,92 @@ where
         self.transitions.write().unwrap().insert(to_step, weight);
     }
 
+    /// Return the weight of the transition to `to_step`, if there is one.
+    pub fn get_transition_weight(&self, to_step: ToStep<T>) -> Option<usize> {
+        self.transitions.read().unwrap().get(&to_step).copied()
+    }
+
+    /// Rescale the outgoing weights so they sum to exactly `target_sum`, keeping their ratios as
+    /// closely as integer weights allow.
+    ///
+    /// Transitions whose weight becomes zero are removed. Steps without outgoing weight are left
+    /// untouched.
+    pub fn normalize_transitions(&self, target_sum: usize) {
+        let mut transitions = self.transitions.write().unwrap();
+        let total: usize = transitions.values().sum();
+        if total == 0 {
+            return;
+        }
+        // Largest remainder method: floor every share, then hand out what is left to the
+        // transitions that lost the most to rounding.
+        let mut shares: Vec<(ToStep<T>, usize, u128)> = transitions
+            .iter()
+            .map(|(to_step, &weight)| {
+                let exact = weight as u128 * target_sum as u128;
+                let floor = (exact / total as u128) as usize;
+                (Arc::clone(to_step), floor, exact % total as u128)
+            })
+            .collect();
+        let assigned: usize = shares.iter().map(|(_, floor, _)| floor).sum();
+        shares.sort_by_key(|share| Reverse(share.2));
+        for share in shares.iter_mut().take(target_sum - assigned) {
+            share.1 += 1;
+        }
+        transitions.clear();
+        transitions.extend(
+            shares
+                .into_iter()
+                .filter(|(_, weight, _)| *weight > 0)
+                .map(|(to_step, weight, _)| (to_step, weight)),
+        );
+    }
+
+    /// Compute the cosine similarity between the outgoing transition distributions of two steps.
+    ///
+    /// Returns `1.0` for identical distributions and `0.0` when the steps share no target
+    /// state or either has no outgoing weight.
+    pub fn transition_similarity(&self, other: &Step<T>) -> f64 {
+        let ours = self.transition_probabilities();
+        let theirs: HashMap<T, f64> = other
+            .transition_probabilities()
+            .into_iter()
+            .map(|(to_step, probability)| (to_step.state, probability))
+            .collect();
+        let dot: f64 = ours
+            .iter()
+            .filter_map(|(to_step, p)| theirs.get(&to_step.state).map(|q| p * q))
+            .sum();
+        let our_norm = ours.iter().map(|(_, p)| p * p).sum::<f64>().sqrt();
+        let their_norm = theirs.values().map(|q| q * q).sum::<f64>().sqrt();
+        if our_norm == 0.0 || their_norm == 0.0 {
+            return 0.0;
+        }
+        dot / (our_norm * their_norm)
+    }
+
+    /// Outgoing transitions paired with their probability, empty if the total weight is zero.
+    pub(crate) fn transition_probabilities(&self) -> Vec<(ToStep<T>, f64)> {
+        let transitions = self.transitions.read().unwrap();
+        let total: usize = transitions.values().sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        transitions
+            .iter()
+            .map(|(to_step, &weight)| (Arc::clone(to_step), weight as f64 / total as f64))
+            .collect()
+    }
+
     /// Randomly select the next step based on transition weights.
     pub fn next(&self) -> Option<ToStep<T>> {
-        let mut rng = rand::rng();
+        self.next_with_rng(&mut rand::rng())
+    }
+
+    /// Randomly select the next step based on transition weights, drawing from `rng`.
+    ///
+    /// Transitions are scanned in the iteration order of this step's map, which is randomized
+    /// per map, so the same `rng` state picks the same step only on this step.
+    pub fn next_with_rng<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<ToStep<T>> {
         let transitions = self.transitions.read().unwrap();
         if transitions.is_empty() {
             return None;
@@ -103,6 +187,38 @@ where
     }
 }
 
+/// An infinite, lazy walk over the Markov chain that yields each visited state.
+///
+/// The iterator only ends once it reaches a step without outgoing transitions.
+pub struct WalkIter<T: Eq + Copy + Hash + Debug + Send + Sync> {
+    current: Option<ToStep<T>>,
+}
+
+impl<T> WalkIter<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+{
+    /// Create a walk beginning at `start`; the first item yielded is `start`'s state.
+    pub fn new(start: ToStep<T>) -> Self {
+        WalkIter {
+            current: Some(start),
+        }
+    }
+}
+
+impl<T> Iterator for WalkIter<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let current = self.current.take()?;
+        self.current = current.next();
+        Some(current.state)
+    }
+}
+
 /// Walk the Markov chain for a fixed number of steps, returning the visited states.
 pub fn walk<T>(start: ToStep<T>, steps: usize) -> Vec<T>
 where
@@ -121,6 +237,37 @@ where
     path
 }
 
+/// Walk the Markov chain for a fixed number of steps using an RNG seeded with `seed`.
+///
+/// Walking the same steps again with the same seed reproduces the same path. A rebuilt copy
+/// of the chain, even one with identical weights, iterates its transitions in a different
+/// order and generally walks a different path.
+pub fn walk_seeded<T>(start: ToStep<T>, steps: usize, seed: u64) -> Vec<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+{
+    walk_with_rng(start, steps, &mut StdRng::seed_from_u64(seed))
+}
+
+/// Walk the Markov chain for a fixed number of steps, drawing every transition from `rng`.
+pub(crate) fn walk_with_rng<T, R>(start: ToStep<T>, steps: usize, rng: &mut R) -> Vec<T>
+where
+    T: Eq + Copy + Hash + Debug + Send + Sync,
+    R: Rng + ?Sized,
+{
+    let mut current = start;
+    let mut path = vec![current.state];
+    for _ in 1..steps {
+        if let Some(next) = current.next_with_rng(rng) {
+            path.push(next.state);
+            current = next;
+        } else {
+            break;
+        }
+    }
+    path
+}
+
 /// Walk the Markov chain for a fixed number of steps, applying a function to each transition.
 ///
 /// The `apply` function is called with the current and next step, and can mutate the chain or collect data.